@@ -1,19 +1,35 @@
 use amethyst::{
     core::{Transform, SystemDesc},
     derive::SystemDesc,
-    ecs::prelude::{Join, ReadStorage, System, SystemData, World, WriteStorage},
+    ecs::prelude::{Join, Read, ReadStorage, System, SystemData, World, WriteStorage},
+    input::{InputHandler, StringBindings},
 };
 use nes_emulator_rs;
+use nes_emulator_rs::input::ButtonState;
 #[derive(SystemDesc)]
 pub struct NesSystem;
 
 impl<'s> System<'s> for NesSystem {
     type SystemData = (
         WriteStorage<'s, crate::component::pixel::NesWrapper>,
+        Read<'s, InputHandler<StringBindings>>,
     );
 
-    fn run(&mut self, (mut nes): Self::SystemData) {
-        for n in nes {
+    fn run(&mut self, (mut nes, input): Self::SystemData) {
+        // config/bindings.ron のキーを 1P のコントローラにする
+        let pressed = |action: &str| input.action_is_down(action).unwrap_or(false);
+        let buttons = ButtonState {
+            a: pressed("a"),
+            b: pressed("b"),
+            select: pressed("select"),
+            start: pressed("start"),
+            up: pressed("up"),
+            down: pressed("down"),
+            left: pressed("left"),
+            right: pressed("right"),
+        };
+        for n in (&mut nes).join() {
+            n.nes.set_buttons(0, buttons);
             n.nes.next();
         }
     }
@@ -0,0 +1,381 @@
+mod dmc;
+mod envelope;
+mod filter;
+mod length_counter;
+mod mixer;
+mod noise;
+mod pulse;
+mod resampler;
+mod triangle;
+
+use dmc::Dmc;
+use mixer::Mixer;
+use noise::Noise;
+use pulse::Pulse;
+use resampler::Resampler;
+use triangle::Triangle;
+
+/// 出力のサンプリング周波数の初期値 (Hz)
+pub const DEFAULT_SAMPLE_RATE: f64 = 44100.0;
+
+/// 4 ステップモードのフレームカウンタ (CPU サイクル)
+/// 29828-29830 で IRQ が立ち, 29830 で 0 に戻る
+const FOUR_STEP_PERIOD: u32 = 29830;
+/// 5 ステップモード, IRQ は起きない
+const FIVE_STEP_PERIOD: u32 = 37282;
+
+/// # APU
+/// $4000-$4013, $4015, $4017
+/// CPU と同じクロックで進める
+/// 矩形波のタイマーは APU サイクル (CPU の 2 サイクル) ごと, 他は CPU サイクルごと
+/// DMC のサンプルの読み込みは CpuBus が dma_address と fill_dmc でおこなう
+/// 出力は CPU サイクルごとに混ぜてホストのサンプリング周波数に落とし, drain_audio で取り出す
+pub struct Apu {
+    pub pulse1: Pulse,
+    pub pulse2: Pulse,
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+    /// $4017 の bit 7
+    five_step: bool,
+    /// $4017 の bit 6
+    irq_inhibit: bool,
+    frame_irq: bool,
+    /// フレームカウンタが何サイクル進んだか
+    frame_cycles: u32,
+    /// $4017 に書き込まれてからフレームカウンタがリセットされるまでの残りサイクル
+    frame_reset: Option<u8>,
+    /// 電源投入からの CPU サイクル数
+    cycles: u64,
+    mixer: Mixer,
+    resampler: Resampler,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            pulse1: Pulse::new(1),
+            pulse2: Pulse::new(2),
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_cycles: 0,
+            frame_reset: None,
+            cycles: 0,
+            mixer: Mixer::new(),
+            resampler: Resampler::new(DEFAULT_SAMPLE_RATE),
+        }
+    }
+
+    /// 出力のサンプリング周波数を変える, たまっているサンプルは捨てる
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.resampler = Resampler::new(sample_rate);
+    }
+
+    /// たまっている音声を out の先頭から詰める
+    /// # Return
+    /// 書き込んだサンプル数
+    pub fn drain_audio(&mut self, out: &mut [f32]) -> usize {
+        self.resampler.drain(out)
+    }
+
+    /// CPU の cycles サイクル分進める
+    /// DMC が DMA を必要としたらそこで止まる
+    /// # Return
+    /// 進めたサイクル数
+    pub fn run(&mut self, cycles: usize) -> usize {
+        for i in 0..cycles {
+            self.step();
+            if self.dmc.dma_address().is_some() {
+                return i + 1;
+            }
+        }
+        cycles
+    }
+
+    /// DMC のサンプルを読むアドレス
+    pub fn dma_address(&self) -> Option<u16> {
+        self.dmc.dma_address()
+    }
+
+    /// DMA で読んだサンプルを DMC に渡す
+    pub fn fill_dmc(&mut self, data: u8) {
+        self.dmc.fill(data);
+    }
+
+    fn step(&mut self) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.cycles % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.clock_frame_counter();
+
+        let sample = self.mixer.mix(
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        );
+        self.resampler.clock(sample);
+        self.cycles += 1;
+    }
+
+    fn clock_frame_counter(&mut self) {
+        if let Some(delay) = self.frame_reset {
+            if delay == 0 {
+                self.frame_reset = None;
+                self.frame_cycles = 0;
+                // 5 ステップモードにするとすぐに quarter frame と half frame が来る
+                if self.five_step {
+                    self.quarter_frame();
+                    self.half_frame();
+                }
+                return;
+            }
+            self.frame_reset = Some(delay - 1);
+        }
+
+        self.frame_cycles += 1;
+        match (self.five_step, self.frame_cycles) {
+            (_, 7457) | (_, 22371) => self.quarter_frame(),
+            (_, 14913) => {
+                self.quarter_frame();
+                self.half_frame();
+            }
+            (false, 29828) => self.set_frame_irq(),
+            (false, 29829) => {
+                self.quarter_frame();
+                self.half_frame();
+                self.set_frame_irq();
+            }
+            (false, FOUR_STEP_PERIOD) => {
+                self.set_frame_irq();
+                self.frame_cycles = 0;
+            }
+            (true, 37281) => {
+                self.quarter_frame();
+                self.half_frame();
+            }
+            (true, FIVE_STEP_PERIOD) => self.frame_cycles = 0,
+            _ => {}
+        }
+    }
+
+    /// エンベロープと三角波の線形カウンタ
+    fn quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.triangle.clock_linear();
+        self.noise.envelope.clock();
+    }
+
+    /// 長さカウンタとスイープ
+    fn half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    fn set_frame_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.frame_irq = true;
+        }
+    }
+
+    /// IRQ 線の状態, フレームカウンタか DMC のサンプルの終わり
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq()
+    }
+
+    /// $4015 の読み込み
+    /// [IF.D NTQP]: u8
+    /// I: DMC 割り込み, F: フレーム割り込み (読むと下りる)
+    /// D: DMC の残りがあるか, N, T, Q, P: 長さカウンタが 0 でないか
+    /// bit 5 は open bus なので呼び出し側で埋める
+    pub fn read_status(&mut self) -> u8 {
+        let status = (self.pulse1.length.is_active() as u8)
+            | (self.pulse2.length.is_active() as u8) << 1
+            | (self.triangle.length.is_active() as u8) << 2
+            | (self.noise.length.is_active() as u8) << 3
+            | (self.dmc.is_active() as u8) << 4
+            | (self.frame_irq as u8) << 6
+            | (self.dmc.irq() as u8) << 7;
+        self.frame_irq = false;
+        status
+    }
+
+    /// $4000-$4013, $4015, $4017 の書き込み
+    /// $4015 に書き込むと DMC 割り込みは下りる
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr & 0x03, data),
+            0x4004..=0x4007 => self.pulse2.write(addr & 0x03, data),
+            0x4008..=0x400b => self.triangle.write(addr & 0x03, data),
+            0x400c..=0x400f => self.noise.write(addr & 0x03, data),
+            0x4010..=0x4013 => self.dmc.write(addr & 0x03, data),
+            // [...D NTQP] 各チャンネルの有効 / 無効
+            0x4015 => {
+                self.dmc.set_enabled(data & 0x10 != 0);
+                self.pulse1.length.set_enabled(data & 0x01 != 0);
+                self.pulse2.length.set_enabled(data & 0x02 != 0);
+                self.triangle.length.set_enabled(data & 0x04 != 0);
+                self.noise.length.set_enabled(data & 0x08 != 0);
+            }
+            // [MI.. ....] M: 5 ステップモード, I: IRQ 禁止
+            // APU サイクルの途中なら 3 サイクル後, そうでなければ 4 サイクル後にリセットされる
+            0x4017 => {
+                self.five_step = data & 0x80 != 0;
+                self.irq_inhibit = data & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_reset = Some(if self.cycles % 2 == 1 { 2 } else { 3 });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_counter() {
+        let mut apu = Apu::new();
+        // 無効なチャンネルにはロードされない
+        apu.write_register(0x4003, 0x08);
+        assert_eq!(apu.read_status() & 0x01, 0x00);
+
+        apu.write_register(0x4015, 0x0f);
+        // 長さ 2 (index 3), ループなし
+        apu.write_register(0x4000, 0x10);
+        apu.write_register(0x4003, 0x18);
+        assert_eq!(apu.read_status() & 0x0f, 0x01);
+        // half frame 2 回で 0 になる
+        apu.run(14913);
+        assert_eq!(apu.read_status() & 0x01, 0x01);
+        apu.run(29829 - 14913);
+        assert_eq!(apu.read_status() & 0x01, 0x00);
+
+        // 無効にするとすぐに 0
+        apu.write_register(0x400f, 0x08);
+        assert_eq!(apu.read_status() & 0x08, 0x08);
+        apu.write_register(0x4015, 0x00);
+        assert_eq!(apu.read_status() & 0x08, 0x00);
+    }
+
+    #[test]
+    fn frame_irq() {
+        let mut apu = Apu::new();
+        apu.run(29827);
+        assert!(!apu.irq());
+        apu.run(1);
+        assert!(apu.irq());
+        // 読むと下りる
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.irq());
+
+        // 5 ステップモードでは起きない
+        apu.write_register(0x4017, 0x80);
+        apu.run(2 * FIVE_STEP_PERIOD as usize);
+        assert!(!apu.irq());
+
+        // IRQ 禁止で下りる
+        apu.write_register(0x4017, 0x00);
+        apu.run(FOUR_STEP_PERIOD as usize + 4);
+        assert!(apu.irq());
+        apu.write_register(0x4017, 0x40);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn pulse_sweep_mute() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4015, 0x01);
+        // デューティ 50%, 固定音量 15
+        apu.write_register(0x4000, 0xbf);
+        apu.write_register(0x4002, 0xff);
+        apu.write_register(0x4003, 0x0f);
+        // $7FF + ($7FF >> 1) は範囲外なのでスイープが無効でも鳴らない
+        apu.write_register(0x4001, 0x01);
+        let outputs: Vec<u8> = (0..0x10000)
+            .map(|_| {
+                apu.run(1);
+                apu.pulse1.output()
+            })
+            .collect();
+        assert!(outputs.iter().all(|&o| o == 0));
+
+        // 下げる方向なら鳴る
+        apu.write_register(0x4001, 0x09);
+        let outputs: Vec<u8> = (0..0x10000)
+            .map(|_| {
+                apu.run(1);
+                apu.pulse1.output()
+            })
+            .collect();
+        assert!(outputs.contains(&15));
+        assert!(outputs.contains(&0));
+    }
+
+    /// DMA の要求に data を返しながら DMC の残りがなくなるまで進める
+    /// # Return
+    /// 読まれたアドレス
+    fn play_dmc(apu: &mut Apu, data: u8, max: usize) -> Vec<u16> {
+        let mut fetched = vec![];
+        while apu.read_status() & 0x10 != 0 && fetched.len() < max {
+            apu.run(1000);
+            if let Some(addr) = apu.dma_address() {
+                fetched.push(addr);
+                apu.fill_dmc(data);
+            }
+        }
+        fetched
+    }
+
+    #[test]
+    fn dmc() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4011, 0x40);
+        assert_eq!(apu.dmc.output(), 0x40);
+
+        // 最速, IRQ 有効, $FFC0 から 65 byte
+        apu.write_register(0x4010, 0x8f);
+        apu.write_register(0x4012, 0xff);
+        apu.write_register(0x4013, 0x04);
+        apu.write_register(0x4015, 0x10);
+        assert_eq!(apu.dma_address(), Some(0xffc0));
+        let fetched = play_dmc(&mut apu, 0xff, 100);
+        // $FFFF の次は $8000
+        assert_eq!(fetched.len(), 65);
+        assert_eq!(fetched[63], 0xffff);
+        assert_eq!(fetched[64], 0x8000);
+        assert!(apu.irq());
+        assert_eq!(apu.read_status() & 0x80, 0x80);
+        // 1 が続くとレベルが上がっていく
+        assert!(apu.dmc.output() > 0x40);
+
+        // $4015 に書き込むと IRQ は下りる
+        apu.write_register(0x4015, 0x00);
+        assert!(!apu.irq());
+
+        // ループするときは IRQ を出さずに最初から読み直す
+        apu.write_register(0x4010, 0xcf);
+        apu.write_register(0x4015, 0x10);
+        let fetched = play_dmc(&mut apu, 0x00, 70);
+        assert_eq!(fetched[65], 0xffc0);
+        assert!(!apu.dmc.irq());
+        assert_eq!(apu.read_status() & 0x10, 0x10);
+    }
+}
@@ -0,0 +1,154 @@
+/// NTSC のタイマー周期 (CPU サイクル)
+const RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+/// デルタ変調チャンネル ($4010-$4013)
+/// CPU のメモリ ($C000-$FFFF) から 1 byte ずつサンプルを DMA で読み,
+/// 1 bit ごとに出力レベルを ±2 する
+#[derive(Default)]
+pub struct Dmc {
+    irq_enabled: bool,
+    loop_flag: bool,
+    timer_period: u16,
+    timer: u16,
+    /// 0-127
+    output_level: u8,
+    /// $4012 で指定されたサンプルの先頭
+    sample_address: u16,
+    /// $4013 で指定されたサンプルの長さ (byte)
+    sample_length: u16,
+    /// 次に DMA で読むアドレス
+    current_address: u16,
+    bytes_remaining: u16,
+    /// DMA で読んだ, まだ出力ユニットに渡していない 1 byte
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    /// 出力ユニットにサンプルがないときはレベルを変えない
+    silence: bool,
+    irq: bool,
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Dmc {
+            timer_period: RATE_TABLE[0],
+            // $4012, $4013 に 0 を書いたのと同じ
+            sample_address: 0xc000,
+            sample_length: 1,
+            bits_remaining: 8,
+            silence: true,
+            ..Dmc::default()
+        }
+    }
+
+    /// reg: アドレスの下位 2bit
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            // [IL.. RRRR] I: IRQ 有効, L: ループ, R: 周期
+            0 => {
+                self.irq_enabled = data & 0x80 != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.loop_flag = data & 0x40 != 0;
+                self.timer_period = RATE_TABLE[data as usize & 0x0f];
+            }
+            // 出力レベルを直接書き換える
+            1 => self.output_level = data & 0x7f,
+            // $C000 + A * 64
+            2 => self.sample_address = 0xc000 | ((data as u16) << 6),
+            // L * 16 + 1 byte
+            _ => self.sample_length = ((data as u16) << 4) + 1,
+        }
+    }
+
+    /// $4015 の bit 4
+    /// 無効にすると残りのサンプルを捨て, 有効にすると残りがなければ最初から再生する
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    /// バッファが空で残りがあれば DMA で読むアドレス
+    pub fn dma_address(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    /// DMA で読んだ 1 byte を受け取る
+    /// $FFFF の次は $8000 に戻る
+    pub fn fill(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        self.current_address = if self.current_address == 0xffff {
+            0x8000
+        } else {
+            self.current_address + 1
+        };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// CPU サイクルごと
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift_register & 0x01 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(data) => {
+                    self.silence = false;
+                    self.shift_register = data;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    /// 0-127
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
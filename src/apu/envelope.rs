@@ -0,0 +1,54 @@
+/// 矩形波とノイズの音量を決めるエンベロープ
+/// quarter frame ごとに 15 から 0 に向かって減衰する
+#[derive(Default)]
+pub struct Envelope {
+    /// $4003 などの書き込みで立ち, 次の quarter frame で 15 からやり直す
+    start: bool,
+    /// 0 まで減衰したら 15 に戻る (長さカウンタの停止フラグと同じビット)
+    loop_flag: bool,
+    /// 減衰せずに period をそのまま音量にする
+    constant: bool,
+    period: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// [..LC VVVV]: u8
+    /// L: ループ, C: 固定音量, V: 音量 / 減衰の周期
+    pub fn write(&mut self, data: u8) {
+        self.loop_flag = data & 0x20 != 0;
+        self.constant = data & 0x10 != 0;
+        self.period = data & 0x0f;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    /// quarter frame
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.period;
+        } else if self.divider == 0 {
+            self.divider = self.period;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.loop_flag {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        if self.constant {
+            self.period
+        } else {
+            self.decay
+        }
+    }
+}
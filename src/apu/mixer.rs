@@ -0,0 +1,31 @@
+/// 5 チャンネルの出力を非線形に混ぜる
+/// 矩形波 2 つと, 三角波・ノイズ・DMC (TND) はそれぞれ別の抵抗網を通るので
+/// nesdev wiki の近似式をテーブルにしておく
+pub struct Mixer {
+    /// index: pulse1 + pulse2 (0-30)
+    pulse_table: Vec<f32>,
+    /// index: 3 * triangle + 2 * noise + dmc (0-202)
+    tnd_table: Vec<f32>,
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        let pulse_table = (0..31)
+            .map(|n| if n == 0 { 0.0 } else { 95.52 / (8128.0 / n as f32 + 100.0) })
+            .collect();
+        let tnd_table = (0..203)
+            .map(|n| if n == 0 { 0.0 } else { 163.67 / (24329.0 / n as f32 + 100.0) })
+            .collect();
+        Mixer {
+            pulse_table,
+            tnd_table,
+        }
+    }
+
+    /// 0.0-1.0 くらい
+    pub fn mix(&self, pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+        let pulse = self.pulse_table[(pulse1 + pulse2) as usize];
+        let tnd = self.tnd_table[3 * triangle as usize + 2 * noise as usize + dmc as usize];
+        pulse + tnd
+    }
+}
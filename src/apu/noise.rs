@@ -0,0 +1,104 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+
+/// NTSC のタイマー周期 (CPU サイクル)
+const PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+/// ノイズチャンネル ($400C-$400F)
+/// 15bit の LFSR の bit 0 が 0 のときに鳴る
+pub struct Noise {
+    /// 短周期モード, bit 0 と bit 6 の XOR を戻す (93 ステップ)
+    /// 通常は bit 0 と bit 1 (32767 ステップ)
+    mode: bool,
+    timer_period: u16,
+    timer: u16,
+    shift: u16,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            mode: false,
+            timer_period: PERIOD_TABLE[0],
+            timer: 0,
+            // 電源投入時は 1
+            shift: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    /// reg: アドレスの下位 2bit
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            // [..LC VVVV]
+            0 => {
+                self.length.set_halt(data & 0x20 != 0);
+                self.envelope.write(data);
+            }
+            1 => {}
+            // [M... PPPP] M: 短周期モード, P: 周期
+            2 => {
+                self.mode = data & 0x80 != 0;
+                self.timer_period = PERIOD_TABLE[data as usize & 0x0f];
+            }
+            // [LLLL L...]
+            _ => {
+                self.length.load(data >> 3);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// CPU サイクルごと
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 0x01;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// 0-15
+    pub fn output(&self) -> u8 {
+        if self.shift & 0x01 != 0 || !self.length.is_active() {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// LFSR が何ステップで元に戻るか
+    fn lfsr_period(noise: &mut Noise) -> Option<usize> {
+        let start = noise.shift;
+        (1..=0x8000).find(|_| {
+            for _ in 0..noise.timer_period {
+                noise.clock_timer();
+            }
+            noise.shift == start
+        })
+    }
+
+    #[test]
+    fn lfsr() {
+        let mut noise = Noise::new();
+        // 短周期モードは 93 ステップで一周する
+        noise.write(2, 0x80);
+        assert_eq!(lfsr_period(&mut noise), Some(93));
+        // 通常モードは 32767 ステップ
+        noise.write(2, 0x00);
+        assert_eq!(lfsr_period(&mut noise), Some(32767));
+    }
+}
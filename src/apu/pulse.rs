@@ -0,0 +1,117 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+
+/// デューティ比 12.5%, 25%, 50%, 25% (反転)
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// 矩形波チャンネル ($4000-$4003, $4004-$4007)
+#[derive(Default)]
+pub struct Pulse {
+    /// 矩形波 1 はスイープで下げるときに 1 の補数を使うので 1 余分に下がる
+    ones_complement: bool,
+    duty: u8,
+    sequence: u8,
+    timer_period: u16,
+    timer: u16,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    /// channel: 1 か 2
+    pub fn new(channel: u8) -> Self {
+        Pulse {
+            ones_complement: channel == 1,
+            ..Pulse::default()
+        }
+    }
+
+    /// reg: アドレスの下位 2bit
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            // [DDLC VVVV] D: デューティ, L: 長さカウンタ停止 / エンベロープのループ
+            0 => {
+                self.duty = data >> 6;
+                self.length.set_halt(data & 0x20 != 0);
+                self.envelope.write(data);
+            }
+            // [EPPP NSSS] E: 有効, P: 周期, N: 下げる, S: シフト量
+            1 => {
+                self.sweep_enabled = data & 0x80 != 0;
+                self.sweep_period = (data >> 4) & 0x07;
+                self.sweep_negate = data & 0x08 != 0;
+                self.sweep_shift = data & 0x07;
+                self.sweep_reload = true;
+            }
+            // タイマーの下位 8bit
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            // [LLLL LTTT] L: 長さ, T: タイマーの上位 3bit
+            _ => {
+                self.timer_period = (self.timer_period & 0x00ff) | ((data as u16 & 0x07) << 8);
+                self.length.load(data >> 3);
+                self.sequence = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// APU サイクル (CPU の 2 サイクル) ごと
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence = (self.sequence + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// スイープで変わった後の周期
+    fn target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if self.sweep_negate {
+            self.timer_period
+                .saturating_sub(change + self.ones_complement as u16)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    /// 周期が 8 未満か, スイープ先が $7FF を超えると鳴らない
+    /// スイープが無効でも判定される
+    fn is_muted(&self) -> bool {
+        self.timer_period < 8 || self.target_period() > 0x07ff
+    }
+
+    /// half frame
+    pub fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.is_muted() {
+            self.timer_period = self.target_period();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    /// 0-15
+    pub fn output(&self) -> u8 {
+        if self.is_muted() || !self.length.is_active() || DUTY_TABLE[self.duty as usize][self.sequence as usize] == 0 {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
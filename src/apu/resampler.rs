@@ -0,0 +1,158 @@
+use super::filter::{HighPass, LowPass};
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+/// NTSC の CPU クロック (Hz)
+pub const CPU_CLOCK_RATE: f64 = 1_789_773.0;
+/// 帯域制限したステップの幅 (出力サンプル数)
+const WIDTH: usize = 16;
+/// サンプルの間のどこで変化したかを何段階で見るか
+const PHASES: usize = 32;
+/// 出力のサンプリング周波数に対するカットオフ (ナイキスト周波数の少し下)
+const CUTOFF: f64 = 0.45;
+
+/// CPU クロックで変化する波形を帯域制限してホストのサンプリング周波数に落とす
+/// 入力が変化したところに帯域制限したインパルスを足し込み, 出力するときに積分する (blip_buf と同じ方式)
+/// 出力は本体と同じハイパス 90 Hz, 440 Hz, ローパス 14 kHz を通してリングバッファにためる
+pub struct Resampler {
+    /// 入力 1 クロックで出力側の時刻がどれだけ進むか
+    step: f64,
+    /// 出力側の時刻 (0.0-1.0), 1.0 を超えたら 1 サンプル確定する
+    time: f64,
+    /// 入力の変化を帯域制限したインパルス, 先頭が次に確定するサンプル
+    deltas: VecDeque<f32>,
+    /// deltas を積分した今の値
+    integrator: f32,
+    last_input: f32,
+    /// [位相][タップ], 位相ごとに合計が 1 になるようにしてある
+    kernels: Vec<[f32; WIDTH]>,
+    high_pass_90: HighPass,
+    high_pass_440: HighPass,
+    low_pass_14k: LowPass,
+    /// まだ読まれていないサンプル, 溢れたら古いものから捨てる
+    samples: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl Resampler {
+    pub fn new(sample_rate: f64) -> Self {
+        let rate = sample_rate as f32;
+        // 0.5 秒分
+        let capacity = sample_rate as usize / 2;
+        Resampler {
+            step: sample_rate / CPU_CLOCK_RATE,
+            time: 0.0,
+            deltas: vec![0.0; WIDTH].into(),
+            integrator: 0.0,
+            last_input: 0.0,
+            kernels: Resampler::build_kernels(),
+            high_pass_90: HighPass::new(90.0, rate),
+            high_pass_440: HighPass::new(440.0, rate),
+            low_pass_14k: LowPass::new(14000.0, rate),
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Blackman 窓をかけた sinc
+    /// 中心を WIDTH / 2 サンプル後ろにずらしているのでその分出力が遅れる
+    fn build_kernels() -> Vec<[f32; WIDTH]> {
+        let half = (WIDTH / 2) as f64;
+        (0..PHASES)
+            .map(|phase| {
+                let offset = phase as f64 / PHASES as f64;
+                let mut kernel = [0.0; WIDTH];
+                for (k, tap) in kernel.iter_mut().enumerate() {
+                    let t = k as f64 + 1.0 - offset - half;
+                    let x = 2.0 * CUTOFF * t;
+                    let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                    let window = 0.42 + 0.5 * (PI * t / half).cos() + 0.08 * (2.0 * PI * t / half).cos();
+                    *tap = (sinc * window) as f32;
+                }
+                let sum: f32 = kernel.iter().sum();
+                for tap in kernel.iter_mut() {
+                    *tap /= sum;
+                }
+                kernel
+            })
+            .collect()
+    }
+
+    /// CPU サイクルごとに呼ぶ
+    pub fn clock(&mut self, input: f32) {
+        if input != self.last_input {
+            let delta = input - self.last_input;
+            let phase = (self.time * PHASES as f64) as usize;
+            let kernel = &self.kernels[phase.min(PHASES - 1)];
+            for (d, k) in self.deltas.iter_mut().zip(kernel.iter()) {
+                *d += delta * k;
+            }
+            self.last_input = input;
+        }
+        self.time += self.step;
+        while self.time >= 1.0 {
+            self.time -= 1.0;
+            self.finish_sample();
+        }
+    }
+
+    fn finish_sample(&mut self) {
+        self.integrator += self.deltas.pop_front().unwrap_or(0.0);
+        self.deltas.push_back(0.0);
+
+        let sample = self.high_pass_90.process(self.integrator);
+        let sample = self.high_pass_440.process(sample);
+        let sample = self.low_pass_14k.process(sample);
+        if self.samples.len() >= self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// たまっているサンプルを out の先頭から詰める
+    /// # Return
+    /// 書き込んだサンプル数
+    pub fn drain(&mut self, out: &mut [f32]) -> usize {
+        let len = out.len().min(self.samples.len());
+        for (o, s) in out.iter_mut().zip(self.samples.drain(..len)) {
+            *o = s;
+        }
+        len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_count() {
+        let mut resampler = Resampler::new(44100.0);
+        for _ in 0..CPU_CLOCK_RATE as usize / 10 {
+            resampler.clock(0.0);
+        }
+        let mut out = vec![0.0; 8192];
+        let len = resampler.drain(&mut out);
+        assert!((4409..=4410).contains(&len));
+        // 読んだ分はなくなる
+        assert_eq!(resampler.drain(&mut out), 0);
+    }
+
+    #[test]
+    fn step_is_band_limited() {
+        let mut resampler = Resampler::new(48000.0);
+        // 1 kHz の矩形波
+        let half_period = CPU_CLOCK_RATE as usize / 2000;
+        for i in 0..CPU_CLOCK_RATE as usize / 10 {
+            resampler.clock(if (i / half_period) % 2 == 0 { 0.0 } else { 0.5 });
+        }
+        let mut out = vec![0.0; 8192];
+        let len = resampler.drain(&mut out);
+        let out = &out[len / 2..len];
+        // ハイパスで直流が抜けて 0 を中心に振れる, リンギングはあっても大きくは超えない
+        let max = out.iter().cloned().fold(f32::MIN, f32::max);
+        let min = out.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(max > 0.25 && max < 0.4, "max: {}", max);
+        assert!(min < -0.25 && min > -0.4, "min: {}", min);
+    }
+}
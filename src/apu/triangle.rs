@@ -0,0 +1,72 @@
+use super::length_counter::LengthCounter;
+
+/// 15 から 0 に下がって 0 から 15 に上がる
+const TRIANGLE_TABLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+/// 三角波チャンネル ($4008-$400B)
+/// 音量はなく, 線形カウンタと長さカウンタの両方が 0 でない間だけ波形が進む
+#[derive(Default)]
+pub struct Triangle {
+    /// 長さカウンタの停止フラグも兼ねる
+    control: bool,
+    linear_period: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    timer_period: u16,
+    timer: u16,
+    sequence: u8,
+    pub length: LengthCounter,
+}
+
+impl Triangle {
+    /// reg: アドレスの下位 2bit
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            // [CRRR RRRR] C: 制御, R: 線形カウンタの値
+            0 => {
+                self.control = data & 0x80 != 0;
+                self.length.set_halt(self.control);
+                self.linear_period = data & 0x7f;
+            }
+            1 => {}
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00ff) | ((data as u16 & 0x07) << 8);
+                self.length.load(data >> 3);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    /// CPU サイクルごと
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length.is_active() {
+                self.sequence = (self.sequence + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// quarter frame
+    pub fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    /// 0-15, 止まっているときは最後の値を出し続ける
+    pub fn output(&self) -> u8 {
+        TRIANGLE_TABLE[self.sequence as usize]
+    }
+}
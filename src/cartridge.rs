@@ -0,0 +1,351 @@
+//
+// iNES / NES 2.0 header
+/*
+| byte   |  description                                          |
++--------+-------------------------------------------------------+
+| 0-3    |  "NES" + 0x1A                                         |
+| 4      |  PRG ROM size (16 KiB 単位)                           |
+| 5      |  CHR ROM size (8 KiB 単位, 0 なら CHR RAM)            |
+| 6      |  [MMMM FTBM] mapper low, four screen, trainer, battery, mirroring |
+| 7      |  [MMMM 10TT] mapper high, NES 2.0 識別子, console type  |
+| 8      |  iNES: PRG RAM size / NES 2.0: [SSSS MMMM] submapper, mapper msb |
+| 9      |  iNES: TV system / NES 2.0: [CCCC PPPP] CHR, PRG ROM size msb |
+| 10     |  NES 2.0: [pppp PPPP] PRG NVRAM, PRG RAM (64 << n bytes) |
+| 11     |  NES 2.0: [cccc CCCC] CHR NVRAM, CHR RAM (64 << n bytes) |
+| 12     |  NES 2.0: CPU/PPU timing                              |
+| 13     |  NES 2.0: Vs. System type / extended console type     |
+| 14     |  NES 2.0: miscellaneous ROMs                          |
+| 15     |  NES 2.0: default expansion device                    |
+*/
+
+use crate::error::{NesError, Result};
+
+pub const NES_HEADER_SIZE: usize = 0x0010;
+pub const TRAINER_SIZE: usize = 0x0200;
+pub const PROGRAM_ROM_SIZE: usize = 0x4000;
+pub const CHARACTER_ROM_SIZE: usize = 0x2000;
+pub const PROGRAM_RAM_SIZE: usize = 0x2000;
+const NES_MAGIC: [u8; 4] = [0x4e, 0x45, 0x53, 0x1a];
+
+/// ヘッダの形式
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum HeaderFormat {
+    /// byte 7-15 が信用できない古い iNES (DiskDude! など)
+    ArchaicINes,
+    INes,
+    Nes20,
+}
+
+/// ネームテーブルのミラーリング
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Mirroring {
+    /// $2000 = $2400, $2800 = $2C00 (縦スクロールのゲーム)
+    Horizontal,
+    /// $2000 = $2800, $2400 = $2C00 (横スクロールのゲーム)
+    Vertical,
+    /// 全部 $2000 を見る
+    SingleScreenLower,
+    /// 全部 $2400 を見る
+    SingleScreenUpper,
+    /// カートリッジ側に追加の VRAM があり 4 画面独立
+    FourScreen,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    Playchoice10,
+    /// NES 2.0 byte 13 の値
+    Extended(u8),
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum TvSystem {
+    Ntsc,
+    Pal,
+    /// NTSC, PAL どちらでも動く
+    MultiRegion,
+    Dendy,
+}
+
+/// カートリッジのヘッダ情報
+/// サイズは全部 byte 単位
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub format: HeaderFormat,
+    pub prg_rom_size: usize,
+    /// 0 なら CHR RAM を使う
+    pub chr_rom_size: usize,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    /// $6000-$7FFF がバッテリーバックアップされている
+    pub battery: bool,
+    /// $7000-$71FF に置かれる 512 byte のトレーナがある
+    pub trainer: bool,
+    pub console_type: ConsoleType,
+    /// 電源を切ると消える PRG RAM
+    pub prg_ram_size: usize,
+    /// バッテリーバックアップされる PRG RAM
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub tv_system: TvSystem,
+    /// NES 2.0 のみ, misc ROM の個数
+    pub misc_roms: u8,
+    /// NES 2.0 のみ, 標準の入力デバイス
+    pub default_expansion_device: u8,
+}
+
+impl Header {
+    /// 先頭 16 byte のヘッダをパースする
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 4 || &bytes[0..4] != &NES_MAGIC {
+            return Err(NesError::BadMagic);
+        }
+        if bytes.len() < NES_HEADER_SIZE {
+            return Err(NesError::Truncated {
+                section: "header",
+                expected: NES_HEADER_SIZE,
+                actual: bytes.len(),
+            });
+        }
+
+        let flags6 = bytes[6];
+        let flags7 = bytes[7];
+        let format = if flags7 & 0x0c == 0x08 {
+            HeaderFormat::Nes20
+        } else if flags7 & 0x0c == 0x00 && bytes[12..16].iter().all(|b| *b == 0) {
+            HeaderFormat::INes
+        } else {
+            HeaderFormat::ArchaicINes
+        };
+
+        let mirroring = if flags6 & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if flags6 & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let battery = flags6 & 0x02 != 0;
+        let trainer = flags6 & 0x04 != 0;
+
+        let mut mapper = (flags6 >> 4) as u16;
+        if format != HeaderFormat::ArchaicINes {
+            mapper |= (flags7 & 0xf0) as u16;
+        }
+
+        let console_type = match (format, flags7 & 0x03) {
+            (HeaderFormat::ArchaicINes, _) => ConsoleType::Nes,
+            (_, 0) => ConsoleType::Nes,
+            (_, 1) => ConsoleType::VsSystem,
+            (_, 2) => ConsoleType::Playchoice10,
+            (HeaderFormat::Nes20, _) => ConsoleType::Extended(bytes[13] & 0x0f),
+            // iNES では 3 は未定義
+            _ => ConsoleType::Nes,
+        };
+
+        if format == HeaderFormat::Nes20 {
+            mapper |= ((bytes[8] & 0x0f) as u16) << 8;
+            let prg_rom_size = Header::nes20_rom_size(bytes[4], bytes[9] & 0x0f, PROGRAM_ROM_SIZE);
+            let chr_rom_size = Header::nes20_rom_size(bytes[5], bytes[9] >> 4, CHARACTER_ROM_SIZE);
+            let shift_size = |n: u8| if n == 0 { 0 } else { 64usize << n };
+            if prg_rom_size == 0 {
+                return Err(NesError::InvalidHeader("PRG ROM size is 0"));
+            }
+            let tv_system = match bytes[12] & 0x03 {
+                0 => TvSystem::Ntsc,
+                1 => TvSystem::Pal,
+                2 => TvSystem::MultiRegion,
+                _ => TvSystem::Dendy,
+            };
+            return Ok(Header {
+                format,
+                prg_rom_size,
+                chr_rom_size,
+                mapper,
+                submapper: bytes[8] >> 4,
+                mirroring,
+                battery,
+                trainer,
+                console_type,
+                prg_ram_size: shift_size(bytes[10] & 0x0f),
+                prg_nvram_size: shift_size(bytes[10] >> 4),
+                chr_ram_size: shift_size(bytes[11] & 0x0f),
+                chr_nvram_size: shift_size(bytes[11] >> 4),
+                tv_system,
+                misc_roms: bytes[14] & 0x03,
+                default_expansion_device: bytes[15] & 0x3f,
+            });
+        }
+
+        // iNES: byte 8 が 0 のときは互換性のため 8 KiB とみなす
+        let prg_ram_pages = if format == HeaderFormat::INes { bytes[8] } else { 0 };
+        let prg_ram_size = PROGRAM_RAM_SIZE * std::cmp::max(prg_ram_pages, 1) as usize;
+        let tv_system = if format == HeaderFormat::INes && bytes[9] & 0x01 != 0 {
+            TvSystem::Pal
+        } else {
+            TvSystem::Ntsc
+        };
+        let chr_rom_size = bytes[5] as usize * CHARACTER_ROM_SIZE;
+        if bytes[4] == 0 {
+            return Err(NesError::InvalidHeader("PRG ROM size is 0"));
+        }
+        Ok(Header {
+            format,
+            prg_rom_size: bytes[4] as usize * PROGRAM_ROM_SIZE,
+            chr_rom_size,
+            mapper,
+            submapper: 0,
+            mirroring,
+            battery,
+            trainer,
+            console_type,
+            prg_ram_size: if battery { 0 } else { prg_ram_size },
+            prg_nvram_size: if battery { prg_ram_size } else { 0 },
+            chr_ram_size: if chr_rom_size == 0 { CHARACTER_ROM_SIZE } else { 0 },
+            chr_nvram_size: 0,
+            tv_system,
+            misc_roms: 0,
+            default_expansion_device: 0,
+        })
+    }
+
+    /// NES 2.0 の ROM サイズ
+    /// msb が 0xF のときは指数表記 [EEEE EEMM]: 2^E * (MM * 2 + 1)
+    fn nes20_rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+        if msb == 0x0f {
+            let exponent = (lsb >> 2) as u32;
+            let multiplier = (lsb & 0x03) as usize * 2 + 1;
+            1usize
+                .checked_shl(exponent)
+                .unwrap_or(usize::MAX)
+                .saturating_mul(multiplier)
+        } else {
+            (((msb as usize) << 8) | lsb as usize) * unit
+        }
+    }
+}
+
+/// カートリッジ
+pub struct Cartridge {
+    pub header: Header,
+    /// $7000-$71FF にロードされる
+    pub trainer: Option<Vec<u8>>,
+    pub prg_rom: Vec<u8>,
+    /// CHR RAM のカートリッジでは空
+    pub chr_rom: Vec<u8>,
+    /// PlayChoice の INST-ROM など, CHR ROM の後ろに続くデータ
+    pub misc_rom: Vec<u8>,
+}
+
+impl Cartridge {
+    /// nesのバイナリをヘッダ, トレーナ, programROM, charactorROMにパースする
+    pub fn parse(binary: &[u8]) -> Result<Self> {
+        let header = Header::parse(binary)?;
+
+        let mut offset = NES_HEADER_SIZE;
+        let trainer = if header.trainer {
+            Some(Cartridge::take(binary, &mut offset, TRAINER_SIZE, "trainer")?)
+        } else {
+            None
+        };
+        let prg_rom = Cartridge::take(binary, &mut offset, header.prg_rom_size, "PRG ROM")?;
+        let chr_rom = Cartridge::take(binary, &mut offset, header.chr_rom_size, "CHR ROM")?;
+        let misc_rom = binary[offset..].to_vec();
+
+        Ok(Cartridge {
+            header,
+            trainer,
+            prg_rom,
+            chr_rom,
+            misc_rom,
+        })
+    }
+
+    /// binary[offset..offset + size] を切り出して offset を進める
+    fn take(binary: &[u8], offset: &mut usize, size: usize, section: &'static str) -> Result<Vec<u8>> {
+        let rest = binary.len() - *offset;
+        if rest < size {
+            return Err(NesError::Truncated {
+                section,
+                expected: size,
+                actual: rest,
+            });
+        }
+        let data = binary[*offset..*offset + size].to_vec();
+        *offset += size;
+        Ok(data)
+    }
+
+    /// CHR ROM を持たず CHR RAM を使うか
+    pub fn has_chr_ram(&self) -> bool {
+        self.header.chr_rom_size == 0
+    }
+}
+
+/// テスト用の iNES ヘッダ, bytes はマジックナンバーの後ろの 12 バイト
+#[cfg(test)]
+pub(crate) fn ines_header(bytes: [u8; 12]) -> Vec<u8> {
+    let mut binary = NES_MAGIC.to_vec();
+    binary.extend(bytes.iter());
+    binary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ines() {
+        let mut binary = ines_header([2, 0, 0x17, 0x10, 0, 1, 0, 0, 0, 0, 0, 0]);
+        binary.extend(vec![0xaa; TRAINER_SIZE]);
+        binary.extend(vec![0xbb; 2 * PROGRAM_ROM_SIZE]);
+        let cartridge = Cartridge::parse(&binary).unwrap();
+        let h = &cartridge.header;
+        assert_eq!(h.format, HeaderFormat::INes);
+        assert_eq!(h.mapper, 0x11);
+        assert_eq!(h.mirroring, Mirroring::Vertical);
+        assert!(h.battery);
+        assert_eq!(h.prg_nvram_size, PROGRAM_RAM_SIZE);
+        assert_eq!(h.tv_system, TvSystem::Pal);
+        assert_eq!(cartridge.trainer.as_ref().map(|t| t.len()), Some(TRAINER_SIZE));
+        assert_eq!(cartridge.prg_rom.len(), 2 * PROGRAM_ROM_SIZE);
+        assert!(cartridge.has_chr_ram());
+        assert_eq!(h.chr_ram_size, CHARACTER_ROM_SIZE);
+    }
+
+    #[test]
+    fn parse_nes20() {
+        let mut binary = ines_header([1, 1, 0x08, 0x48, 0x21, 0x00, 0x07, 0x70, 0x01, 0, 0, 1]);
+        binary.extend(vec![0; PROGRAM_ROM_SIZE + CHARACTER_ROM_SIZE]);
+        let h = Cartridge::parse(&binary).unwrap().header;
+        assert_eq!(h.format, HeaderFormat::Nes20);
+        assert_eq!(h.mapper, 0x140);
+        assert_eq!(h.submapper, 2);
+        assert_eq!(h.mirroring, Mirroring::FourScreen);
+        assert_eq!(h.prg_ram_size, 64 << 7);
+        assert_eq!(h.chr_nvram_size, 64 << 7);
+        assert_eq!(h.tv_system, TvSystem::Pal);
+        assert_eq!(h.default_expansion_device, 1);
+        assert_eq!(Header::nes20_rom_size(0x09, 0x0f, PROGRAM_ROM_SIZE), 4 * 3);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(Cartridge::parse(b"NEX\x1a"), Err(NesError::BadMagic)));
+        assert!(matches!(Cartridge::parse(&NES_MAGIC), Err(NesError::Truncated { .. })));
+        let mut binary = ines_header([2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        binary.extend(vec![0; PROGRAM_ROM_SIZE]);
+        match Cartridge::parse(&binary) {
+            Err(NesError::Truncated { section, expected, actual }) => {
+                assert_eq!(section, "PRG ROM");
+                assert_eq!(expected, 2 * PROGRAM_ROM_SIZE);
+                assert_eq!(actual, PROGRAM_ROM_SIZE);
+            }
+            _ => panic!("expected truncated error"),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::cpu_bus::{self};
 
 pub mod op;
+#[cfg(test)]
 mod tests;
 struct Register {
     A: u8,	                // 8bit	アキュームレータ	汎用演算
@@ -87,6 +88,9 @@ impl Interrupts {
     }
 }
 
+/// XAA, LXA で A に OR される値, 実機では個体や温度で変わる
+const UNSTABLE_MAGIC: u8 = 0xee;
+
 #[derive(Debug, Copy, Clone)]
 enum Operand {
     None,
@@ -108,6 +112,16 @@ pub struct Cpu {
     interrupts: Interrupts,
 
     cpu_bus: cpu_bus::CpuBus,
+
+    /// 電源投入からのサイクル数
+    /// OAM DMA の待ちサイクルが偶数か奇数かで変わる
+    cycles: u64,
+
+    /// 直前の fetch_operand でインデックスを足したときにページをまたいだか
+    page_crossed: bool,
+
+    /// KIL で止まっている
+    jammed: bool,
 }
 
 impl Cpu {
@@ -117,27 +131,61 @@ impl Cpu {
             register: Register::new(),
             interrupts: Interrupts::new(),
             cpu_bus,
+            cycles: 0,
+            page_crossed: false,
+            jammed: false,
         }
     }
 
     /// CPUの実行
     /// 実行タイミング調整のために実行にかかったサイクル数を返す
-    pub fn run(&mut self) -> u8 {
+    pub fn run(&mut self) -> u16 {
+        // KIL の後は割り込みも受け付けずに止まったまま, PPU と APU だけ進める
+        if self.jammed {
+            self.cpu_bus.begin_instruction(1);
+            let cycles = 1 + self.cpu_bus.end_instruction(1);
+            self.cycles += cycles as u64;
+            return cycles;
+        }
+
         // process interruption
         let pc = self.register.PC;
+        // IRQ はカートリッジなどからのレベルトリガなので毎回バスの状態を見る
+        self.interrupts.irq = self.cpu_bus.irq();
+        // NMI は PPU の出力の立ち上がりで起きる
+        if self.cpu_bus.take_nmi() {
+            self.interrupts.nmi = true;
+        }
+
+        // 割り込みの処理に 7 サイクルかかる
+        let mut cycles: u16 = 0;
         // 僕ウェブさんはinterruptsを消費していた
         if self.interrupts.nmi {
             self.interrupt(op::Interrupt::NMI);
-        }
-        if self.interrupts.irq {
+            cycles += 7;
+        } else if self.interrupts.irq && !self.register.P.interrupt {
             self.interrupt(op::Interrupt::IRQ);
+            cycles += 7;
         }
 
         let opcode = self.fetch();
         let instruction = op::decode_op(opcode);
         let operand = self.fetch_operand(instruction.1);
+        // インデックスを足してページをまたぐ読み込みは 1 サイクル遅れてアクセスする
+        let penalty = (self.page_crossed && instruction.0.has_page_cross_penalty()) as u16;
+        self.cpu_bus.begin_instruction(cycles + instruction.2 as u16 + penalty);
         // println!("PC {:x}: {:?} {:?}", pc, instruction.0, instruction.1);
-        self.exec(instruction, operand)
+        cycles += self.exec(instruction, operand) as u16 + penalty;
+
+        // OAM DMA の間 CPU は止まる
+        // 書き込みの完了待ちで 1 サイクル, 奇数サイクルならさらに 1 サイクル, その後 256 回読み書きする
+        if self.cpu_bus.take_oam_dma() {
+            cycles += if (self.cycles + cycles as u64) % 2 == 1 { 514 } else { 513 };
+        }
+        // DMC の DMA の間も CPU は止まる
+        cycles += self.cpu_bus.end_instruction(cycles);
+        self.cycles += cycles as u64;
+        cycles
     }
 
     /// cpu_busからbyteデータをfetchするレジスタとプラグラムカウンタを上げる
@@ -151,6 +199,7 @@ impl Cpu {
     /// レジスタの初期化
     fn reset(&mut self) {
         self.register = Register::new();
+        self.jammed = false;
     }
 
     /// スタックにプッシュ(下方向に伸びる)
@@ -187,11 +236,6 @@ impl Cpu {
         self.register.PC += (a).rotate_left(8);
     }
 
-    /// NMI flag
-    pub fn set_nmi_flag(&mut self) {
-        self.interrupts.nmi = true;
-    }
-
     /// 割り込み
     // ??: popstatus誰がいつ実行するのか 割り込みベクタの飛んだ先のアドレスでrtiが実行されるのでは?
     pub fn interrupt(&mut self, interruption: op::Interrupt) {
@@ -272,12 +316,73 @@ impl Cpu {
         cycles
     }
 
+    /// Read-Modify-Write 命令の書き込み
+    /// 実機は読んだ値を一度そのまま書き戻してから結果を書くので, 同じアドレスに 2 回書き込む
+    fn write_modified(&mut self, addr: u16, data: u8, data_written: u8) -> u8 {
+        self.cpu_bus.write(addr, data);
+        self.cpu_bus.write(addr, data_written)
+    }
+
+    /// 非公式命令の読み書きする実効アドレス
+    fn effective_addr(mode: op::AddressingMode, operand: Operand) -> u16 {
+        match (mode, operand) {
+            (op::AddressingMode::Zeropage, Operand::Byte(byte)) |
+            (op::AddressingMode::ZeropageX, Operand::Byte(byte)) |
+            (op::AddressingMode::ZeropageY, Operand::Byte(byte)) => byte as u16,
+            (op::AddressingMode::Absolute, Operand::Word(word)) |
+            (op::AddressingMode::AbsoluteX, Operand::Word(word)) |
+            (op::AddressingMode::AbsoluteY, Operand::Word(word)) |
+            (op::AddressingMode::IndirectIndexed, Operand::Word(word)) |
+            (op::AddressingMode::IndexedIndirect, Operand::Word(word)) => word,
+            _ => panic!("error mode: {:?}, operand: {:?}", mode, operand)
+        }
+    }
+
+    /// 非公式命令のオペランドの値, Immediate ならそのまま, それ以外はメモリから読む
+    fn read_operand(&mut self, mode: op::AddressingMode, operand: Operand) -> u8 {
+        match (mode, operand) {
+            (op::AddressingMode::Immediate, Operand::Byte(byte)) => byte,
+            _ => self.cpu_bus.read(Self::effective_addr(mode, operand)),
+        }
+    }
+
+    fn set_nz(&mut self, data: u8) {
+        self.register.P.negative = data & 0x80 != 0;
+        self.register.P.zero = data == 0;
+    }
+
+    /// A = A + data + C
+    /// SBC は data を反転して足すのと同じ
+    fn add_with_carry(&mut self, data: u8) {
+        let a = self.register.A;
+        let result = a as u16 + data as u16 + self.register.P.carry as u16;
+        self.register.P.carry = result > 0xff;
+        self.register.P.overflow = (a ^ result as u8) & (data ^ result as u8) & 0x80 != 0;
+        self.register.A = result as u8;
+        self.set_nz(self.register.A);
+    }
+
+    /// SHY, SHX, AHX, TAS のストア
+    /// インデックスを足す前のアドレスの上位 + 1 との AND を書き込む
+    /// ページをまたいだときはアドレスの上位も書き込む値に化ける
+    fn store_high_and(&mut self, addr: u16, index: u8, data: u8) {
+        let high = (addr.wrapping_sub(index as u16) >> 8) as u8;
+        let data = data & high.wrapping_add(1);
+        let addr = if self.page_crossed {
+            (data as u16) << 8 | (addr & 0x00ff)
+        } else {
+            addr
+        };
+        self.cpu_bus.write(addr, data);
+    }
+
     /// fetch_operandはアドレッシングモードからアドレスを返す
     /// アドレスを返さない場合があるのでそのときはNoneを返す
     /// 返り値のu16はほとんどがu8で済むが一部のアドレッシングモードにおいてu16を返す必要があります
     fn fetch_operand(&mut self, mode: op::AddressingMode) -> Operand {
 
         // TODO: テストをしよう
+        self.page_crossed = false;
         match mode {
             op::AddressingMode::Accumulator => Operand::None,
             op::AddressingMode::Implied => Operand::None,
@@ -304,7 +409,8 @@ impl Cpu {
                 let low = self.cpu_bus.read(addr_or_data) as u16;
                 let hi = (self.cpu_bus.read((addr_or_data + 1) & 0x00ff)) as u16;
                 let base_addr = (hi << 8) | low;
-                let addr = base_addr + self.register.Y as u16;
+                let addr = base_addr.wrapping_add(self.register.Y as u16);
+                self.page_crossed = Self::pages_diff(base_addr, addr);
                 Operand::Word(addr)
             },
             op::AddressingMode::AbsoluteIndirect => {
@@ -329,14 +435,18 @@ impl Cpu {
                 let low = self.fetch() as u16;
                 let hi = self.fetch() as u16;
                 let addr_or_data = (hi << 8) | low;
-                Operand::Word(addr_or_data + self.register.X as u16)
+                let addr = addr_or_data.wrapping_add(self.register.X as u16);
+                self.page_crossed = Self::pages_diff(addr_or_data, addr);
+                Operand::Word(addr)
             },
             op::AddressingMode::AbsoluteY => {
                 // [high: 8, low: 8] + Y
                 let low = self.fetch() as u16;
                 let hi = self.fetch() as u16;
                 let addr_or_data = (hi << 8) | low;
-                Operand::Word(addr_or_data + self.register.Y as u16)
+                let addr = addr_or_data.wrapping_add(self.register.Y as u16);
+                self.page_crossed = Self::pages_diff(addr_or_data, addr);
+                Operand::Word(addr)
             },
             op::AddressingMode::Relative => {
                 // 符号拡張のためi8にcast
@@ -650,12 +760,12 @@ impl Cpu {
                     (op::AddressingMode::Zeropage, Operand::Byte(byte)) |
                     (op::AddressingMode::ZeropageX, Operand::Byte(byte)) => {
                         let data = self.cpu_bus.read(byte as u16);
-                        self.cpu_bus.write(byte as u16, data.wrapping_add(1))
+                        self.write_modified(byte as u16, data, data.wrapping_add(1))
                     }
                     (op::AddressingMode::Absolute, Operand::Word(word)) |
                     (op::AddressingMode::AbsoluteX, Operand::Word(word)) => {
                         let data = self.cpu_bus.read(word);
-                        self.cpu_bus.write(word, data.wrapping_add(1))
+                        self.write_modified(word, data, data.wrapping_add(1))
                     }
                     _ => panic!("error, mode: {:?}, operand: {:?}", mode, operand)
                 };
@@ -669,12 +779,12 @@ impl Cpu {
                     (op::AddressingMode::Zeropage, Operand::Byte(byte)) |
                     (op::AddressingMode::ZeropageX, Operand::Byte(byte)) => {
                         let data = self.cpu_bus.read(byte as u16);
-                        self.cpu_bus.write(byte as u16, data.wrapping_sub(1))
+                        self.write_modified(byte as u16, data, data.wrapping_sub(1))
                     }
                     (op::AddressingMode::Absolute, Operand::Word(word)) |
                     (op::AddressingMode::AbsoluteX, Operand::Word(word)) => {
                         let data = self.cpu_bus.read(word);
-                        self.cpu_bus.write(word, data.wrapping_sub(1))
+                        self.write_modified(word, data, data.wrapping_sub(1))
                     }
                     _ => panic!("error, mode: {:?}, operand: {:?}", mode, operand)
                 };
@@ -837,19 +947,17 @@ impl Cpu {
                     }
                     (op::AddressingMode::Zeropage, Operand::Byte(byte)) |
                     (op::AddressingMode::ZeropageX, Operand::Byte(byte)) => {
-                        let mut data = self.cpu_bus.read(byte as u16);
+                        let data = self.cpu_bus.read(byte as u16);
                         self.register.P.carry = data & 0b10000_000 != 0;
-                        data =  data << 1;
-                        self.cpu_bus.write(byte as u16, data);
+                        let data = self.write_modified(byte as u16, data, data << 1);
                         self.register.P.zero = data == 0;
                         self.register.P.negative = data & 0b10000_000 != 0;
                     }
                     (op::AddressingMode::Absolute, Operand::Word(word)) |
                     (op::AddressingMode::AbsoluteX, Operand::Word(word)) => {
-                        let mut data = self.cpu_bus.read(word);
+                        let data = self.cpu_bus.read(word);
                         self.register.P.carry = data & 0b10000_000 != 0;
-                        data =  data << 1;
-                        self.cpu_bus.write(word, data);
+                        let data = self.write_modified(word, data, data << 1);
                         self.register.P.zero = data == 0;
                         self.register.P.negative = data & 0b10000_000 != 0;
                     }
@@ -866,19 +974,17 @@ impl Cpu {
                     }
                     (op::AddressingMode::Zeropage, Operand::Byte(byte)) |
                     (op::AddressingMode::ZeropageX, Operand::Byte(byte)) => {
-                        let mut data = self.cpu_bus.read(byte as u16);
+                        let data = self.cpu_bus.read(byte as u16);
                         self.register.P.carry = data & 0b00000_001 != 0;
-                        data =  data >> 1;
-                        self.cpu_bus.write(byte as u16, data);
+                        let data = self.write_modified(byte as u16, data, data >> 1);
                         self.register.P.zero = data == 0;
                         self.register.P.negative = data & 0b10000_000 != 0;
                     }
                     (op::AddressingMode::Absolute, Operand::Word(word)) |
                     (op::AddressingMode::AbsoluteX, Operand::Word(word)) => {
-                        let mut data = self.cpu_bus.read(word);
+                        let data = self.cpu_bus.read(word);
                         self.register.P.carry = data & 0b00000_001 != 0;
-                        data =  data >> 1;
-                        self.cpu_bus.write(word, data);
+                        let data = self.write_modified(word, data, data >> 1);
                         self.register.P.zero = data == 0;
                         self.register.P.negative = data & 0b10000_000 != 0;
                     }
@@ -898,7 +1004,7 @@ impl Cpu {
                     (op::AddressingMode::ZeropageX, Operand::Byte(byte)) => {
                         let data = self.cpu_bus.read(byte as u16);
                         let data_written = data.rotate_left(1);
-                        self.cpu_bus.write(byte as u16, data_written);
+                        self.write_modified(byte as u16, data, data_written);
                         self.register.P.carry = data & 0x80 != 0;
                         self.register.P.zero = data_written == 0;
                         self.register.P.negative = data_written & 0x80 != 0;
@@ -907,7 +1013,7 @@ impl Cpu {
                     (op::AddressingMode::AbsoluteX, Operand::Word(word)) => {
                         let data = self.cpu_bus.read(word);
                         let data_written = data.rotate_left(1);
-                        self.cpu_bus.write(word, data_written);
+                        self.write_modified(word, data, data_written);
                         self.register.P.carry = data & 0x80 != 0;
                         self.register.P.zero = data_written == 0;
                         self.register.P.negative = data_written & 0x80 != 0;
@@ -928,7 +1034,7 @@ impl Cpu {
                     (op::AddressingMode::ZeropageX, Operand::Byte(byte)) => {
                         let data = self.cpu_bus.read(byte as u16);
                         let data_written = data.rotate_right(1);
-                        self.cpu_bus.write(byte as u16, data_written);
+                        self.write_modified(byte as u16, data, data_written);
                         self.register.P.carry = data & 0x01 != 0;
                         self.register.P.zero = data_written == 0;
                         self.register.P.negative = data_written & 0x80 != 0;
@@ -937,7 +1043,7 @@ impl Cpu {
                     (op::AddressingMode::AbsoluteX, Operand::Word(word)) => {
                         let data = self.cpu_bus.read(word);
                         let data_written = data.rotate_right(1);
-                        self.cpu_bus.write(word, data_written);
+                        self.write_modified(word, data, data_written);
                         self.register.P.carry = data & 0x01 != 0;
                         self.register.P.zero = data_written == 0;
                         self.register.P.negative = data_written & 0x80 != 0;
@@ -1256,10 +1362,146 @@ impl Cpu {
             }
             op::OpCode::NOP => {
                 match (mode, operand) {
-                    (op::AddressingMode::Implied, Operand::None) => { }
-                    _ => panic!("error opcode: {:?}, mode: {:?}", mode, operand)
+                    (op::AddressingMode::Implied, Operand::None) |
+                    (op::AddressingMode::Immediate, Operand::Byte(_)) => { }
+                    // 非公式の NOP はオペランドのアドレスを読むだけ読む
+                    _ => {
+                        self.read_operand(mode, operand);
+                    }
                 }
             }
+
+            // 非公式命令
+            // see <https://www.nesdev.org/wiki/Programming_with_unofficial_opcodes>
+            op::OpCode::SLO => {
+                let addr = Self::effective_addr(mode, operand);
+                let data = self.cpu_bus.read(addr);
+                let data_written = data << 1;
+                self.write_modified(addr, data, data_written);
+                self.register.P.carry = data & 0x80 != 0;
+                self.register.A |= data_written;
+                self.set_nz(self.register.A);
+            }
+            op::OpCode::RLA => {
+                let addr = Self::effective_addr(mode, operand);
+                let data = self.cpu_bus.read(addr);
+                let data_written = data << 1 | self.register.P.carry as u8;
+                self.write_modified(addr, data, data_written);
+                self.register.P.carry = data & 0x80 != 0;
+                self.register.A &= data_written;
+                self.set_nz(self.register.A);
+            }
+            op::OpCode::SRE => {
+                let addr = Self::effective_addr(mode, operand);
+                let data = self.cpu_bus.read(addr);
+                let data_written = data >> 1;
+                self.write_modified(addr, data, data_written);
+                self.register.P.carry = data & 0x01 != 0;
+                self.register.A ^= data_written;
+                self.set_nz(self.register.A);
+            }
+            op::OpCode::RRA => {
+                let addr = Self::effective_addr(mode, operand);
+                let data = self.cpu_bus.read(addr);
+                let data_written = data >> 1 | (self.register.P.carry as u8) << 7;
+                self.write_modified(addr, data, data_written);
+                self.register.P.carry = data & 0x01 != 0;
+                self.add_with_carry(data_written);
+            }
+            op::OpCode::SAX => {
+                let addr = Self::effective_addr(mode, operand);
+                self.cpu_bus.write(addr, self.register.A & self.register.X);
+            }
+            op::OpCode::LAX => {
+                let data = self.read_operand(mode, operand);
+                self.register.A = data;
+                self.register.X = data;
+                self.set_nz(data);
+            }
+            op::OpCode::DCP => {
+                let addr = Self::effective_addr(mode, operand);
+                let data = self.cpu_bus.read(addr);
+                let data_written = data.wrapping_sub(1);
+                self.write_modified(addr, data, data_written);
+                let a = self.register.A;
+                // N Z C
+                self.register.P.carry = a >= data_written;
+                self.set_nz(a.wrapping_sub(data_written));
+            }
+            op::OpCode::ISC => {
+                let addr = Self::effective_addr(mode, operand);
+                let data = self.cpu_bus.read(addr);
+                let data_written = data.wrapping_add(1);
+                self.write_modified(addr, data, data_written);
+                self.add_with_carry(!data_written);
+            }
+            op::OpCode::ANC => {
+                self.register.A &= self.read_operand(mode, operand);
+                self.set_nz(self.register.A);
+                self.register.P.carry = self.register.P.negative;
+            }
+            op::OpCode::ALR => {
+                let data = self.register.A & self.read_operand(mode, operand);
+                self.register.P.carry = data & 0x01 != 0;
+                self.register.A = data >> 1;
+                self.set_nz(self.register.A);
+            }
+            op::OpCode::ARR => {
+                let data = self.register.A & self.read_operand(mode, operand);
+                self.register.A = data >> 1 | (self.register.P.carry as u8) << 7;
+                self.set_nz(self.register.A);
+                // C は bit 6, V は bit 6 xor bit 5
+                self.register.P.carry = self.register.A & 0x40 != 0;
+                self.register.P.overflow = ((self.register.A >> 6) ^ (self.register.A >> 5)) & 0x01 != 0;
+            }
+            op::OpCode::AXS => {
+                let data = self.read_operand(mode, operand);
+                let ax = self.register.A & self.register.X;
+                self.register.P.carry = ax >= data;
+                self.register.X = ax.wrapping_sub(data);
+                self.set_nz(self.register.X);
+            }
+            op::OpCode::XAA => {
+                let data = self.read_operand(mode, operand);
+                self.register.A = (self.register.A | UNSTABLE_MAGIC) & self.register.X & data;
+                self.set_nz(self.register.A);
+            }
+            op::OpCode::LXA => {
+                let data = self.read_operand(mode, operand);
+                self.register.A = (self.register.A | UNSTABLE_MAGIC) & data;
+                self.register.X = self.register.A;
+                self.set_nz(self.register.A);
+            }
+            op::OpCode::AHX => {
+                let addr = Self::effective_addr(mode, operand);
+                let data = self.register.A & self.register.X;
+                self.store_high_and(addr, self.register.Y, data);
+            }
+            op::OpCode::TAS => {
+                let addr = Self::effective_addr(mode, operand);
+                self.register.S = self.register.A & self.register.X;
+                self.store_high_and(addr, self.register.Y, self.register.S);
+            }
+            op::OpCode::SHY => {
+                let addr = Self::effective_addr(mode, operand);
+                self.store_high_and(addr, self.register.X, self.register.Y);
+            }
+            op::OpCode::SHX => {
+                let addr = Self::effective_addr(mode, operand);
+                self.store_high_and(addr, self.register.Y, self.register.X);
+            }
+            op::OpCode::LAS => {
+                let data = self.read_operand(mode, operand) & self.register.S;
+                self.register.A = data;
+                self.register.X = data;
+                self.register.S = data;
+                self.set_nz(data);
+            }
+            op::OpCode::KIL => {
+                // 同じ命令を指したまま止める
+                self.register.PC -= 1;
+                self.jammed = true;
+            }
             // template
             // op::OpCode::XXX => {
             //     match (mode, operand) {
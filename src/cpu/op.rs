@@ -171,6 +171,71 @@ pub enum OpCode {
     PLP,
     // noop
     NOP,
+
+    // 非公式命令
+    // see <https://www.nesdev.org/wiki/CPU_unofficial_opcodes>
+    /// ASL + ORA
+    SLO,
+    /// ROL + AND
+    RLA,
+    /// LSR + EOR
+    SRE,
+    /// ROR + ADC
+    RRA,
+    /// A & X をストア
+    SAX,
+    /// LDA + LDX
+    LAX,
+    /// DEC + CMP
+    DCP,
+    /// INC + SBC
+    ISC,
+    /// AND して N を C にコピー
+    ANC,
+    /// AND + LSR
+    ALR,
+    /// AND + ROR, C と V が特殊
+    ARR,
+    /// X = (A & X) - imm, CMP と同じく borrow なし
+    AXS,
+    /// 不安定: A = (A | magic) & X & imm
+    XAA,
+    /// 不安定: A = X = (A | magic) & imm
+    LXA,
+    /// 不安定: A & X & (アドレス上位 + 1) をストア
+    AHX,
+    /// 不安定: S = A & X, S & (アドレス上位 + 1) をストア
+    TAS,
+    /// 不安定: Y & (アドレス上位 + 1) をストア
+    SHY,
+    /// 不安定: X & (アドレス上位 + 1) をストア
+    SHX,
+    /// A = X = S = メモリ & S
+    LAS,
+    /// CPU が止まる, リセットするまで何もしない
+    KIL,
+}
+
+impl OpCode {
+    /// インデックスを足してページをまたいだときに 1 サイクル増える命令
+    /// 書き込みや Read-Modify-Write の命令は最初から多めにかかるので増えない
+    pub fn has_page_cross_penalty(self) -> bool {
+        matches!(
+            self,
+            OpCode::ADC
+                | OpCode::SBC
+                | OpCode::AND
+                | OpCode::ORA
+                | OpCode::EOR
+                | OpCode::CMP
+                | OpCode::LDA
+                | OpCode::LDX
+                | OpCode::LDY
+                | OpCode::NOP
+                | OpCode::LAX
+                | OpCode::LAS
+        )
+    }
 }
 
 type Cycles = u8;
@@ -193,198 +258,301 @@ const cycles: [u8; 0x100] = [
     /*0xB0*/ 2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4,
     /*0xC0*/ 2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
     /*0xD0*/ 2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
-    /*0xE0*/ 2, 6, 3, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
+    /*0xE0*/ 2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
     /*0xF0*/ 2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
 ];
 
 
-// TODO: メモリロードしたときにサイクル数増えたりするやつがあるので対応する
+/// ページをまたいだときに増えるサイクル数は Cpu が足す
 /// see <https://qiita.com/bokuweb/items/1575337bef44ae82f4d3#%E5%91%BD%E4%BB%A4%E3%82%BB%E3%83%83%E3%83%88>
 pub fn decode_op(op: u8) -> Instruction {
     match op {
         // 0x0X
         0x00 => Instruction(OpCode::BRK, AddressingMode::Implied, cycles[op as usize]),
         0x01 => Instruction(OpCode::ORA, AddressingMode::IndexedIndirect, cycles[op as usize]),
+        0x02 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0x03 => Instruction(OpCode::SLO, AddressingMode::IndexedIndirect, cycles[op as usize]),
+        0x04 => Instruction(OpCode::NOP, AddressingMode::Zeropage, cycles[op as usize]),
         0x05 => Instruction(OpCode::ORA, AddressingMode::Zeropage, cycles[op as usize]),
         0x06 => Instruction(OpCode::ASL, AddressingMode::Zeropage, cycles[op as usize]),
+        0x07 => Instruction(OpCode::SLO, AddressingMode::Zeropage, cycles[op as usize]),
         0x08 => Instruction(OpCode::PHP, AddressingMode::Implied, cycles[op as usize]),
         0x09 => Instruction(OpCode::ORA, AddressingMode::Immediate, cycles[op as usize]),
         0x0a => Instruction(OpCode::ASL, AddressingMode::Accumulator, cycles[op as usize]),
+        0x0b => Instruction(OpCode::ANC, AddressingMode::Immediate, cycles[op as usize]),
+        0x0c => Instruction(OpCode::NOP, AddressingMode::Absolute, cycles[op as usize]),
         0x0d => Instruction(OpCode::ORA, AddressingMode::Absolute, cycles[op as usize]),
         0x0e => Instruction(OpCode::ASL, AddressingMode::Absolute, cycles[op as usize]),
+        0x0f => Instruction(OpCode::SLO, AddressingMode::Absolute, cycles[op as usize]),
 
         // 0x1X
         0x10 => Instruction(OpCode::BPL, AddressingMode::Relative, cycles[op as usize]),
         0x11 => Instruction(OpCode::ORA, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0x12 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0x13 => Instruction(OpCode::SLO, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0x14 => Instruction(OpCode::NOP, AddressingMode::ZeropageX, cycles[op as usize]),
         0x15 => Instruction(OpCode::ORA, AddressingMode::ZeropageX, cycles[op as usize]),
         0x16 => Instruction(OpCode::ASL, AddressingMode::ZeropageX, cycles[op as usize]),
+        0x17 => Instruction(OpCode::SLO, AddressingMode::ZeropageX, cycles[op as usize]),
         0x18 => Instruction(OpCode::CLC, AddressingMode::Implied, cycles[op as usize]),
         0x19 => Instruction(OpCode::ORA, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0x1a => Instruction(OpCode::NOP, AddressingMode::Implied, cycles[op as usize]),
+        0x1b => Instruction(OpCode::SLO, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0x1c => Instruction(OpCode::NOP, AddressingMode::AbsoluteX, cycles[op as usize]),
         0x1d => Instruction(OpCode::ORA, AddressingMode::AbsoluteX, cycles[op as usize]),
         0x1e => Instruction(OpCode::ASL, AddressingMode::AbsoluteX, cycles[op as usize]),
+        0x1f => Instruction(OpCode::SLO, AddressingMode::AbsoluteX, cycles[op as usize]),
 
         // 0x2X
         0x20 => Instruction(OpCode::JSR, AddressingMode::Absolute, cycles[op as usize]),
         0x21 => Instruction(OpCode::AND, AddressingMode::IndexedIndirect, cycles[op as usize]),
+        0x22 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0x23 => Instruction(OpCode::RLA, AddressingMode::IndexedIndirect, cycles[op as usize]),
         0x24 => Instruction(OpCode::BIT, AddressingMode::Zeropage, cycles[op as usize]),
         0x25 => Instruction(OpCode::AND, AddressingMode::Zeropage, cycles[op as usize]),
         0x26 => Instruction(OpCode::ROR, AddressingMode::Zeropage, cycles[op as usize]),
+        0x27 => Instruction(OpCode::RLA, AddressingMode::Zeropage, cycles[op as usize]),
         0x28 => Instruction(OpCode::PLP, AddressingMode::Implied, cycles[op as usize]),
         0x29 => Instruction(OpCode::AND, AddressingMode::Immediate, cycles[op as usize]),
         0x2a => Instruction(OpCode::ROR, AddressingMode::Accumulator, cycles[op as usize]),
+        0x2b => Instruction(OpCode::ANC, AddressingMode::Immediate, cycles[op as usize]),
         0x2c => Instruction(OpCode::BIT, AddressingMode::Absolute, cycles[op as usize]),
         0x2d => Instruction(OpCode::AND, AddressingMode::Absolute, cycles[op as usize]),
         0x2e => Instruction(OpCode::ROR, AddressingMode::Absolute, cycles[op as usize]),
+        0x2f => Instruction(OpCode::RLA, AddressingMode::Absolute, cycles[op as usize]),
 
         // 0x3X
         0x30 => Instruction(OpCode::BMI, AddressingMode::Relative, cycles[op as usize]),
         0x31 => Instruction(OpCode::AND, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0x32 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0x33 => Instruction(OpCode::RLA, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0x34 => Instruction(OpCode::NOP, AddressingMode::ZeropageX, cycles[op as usize]),
         0x35 => Instruction(OpCode::AND, AddressingMode::ZeropageX, cycles[op as usize]),
         0x36 => Instruction(OpCode::ROL, AddressingMode::ZeropageX, cycles[op as usize]),
+        0x37 => Instruction(OpCode::RLA, AddressingMode::ZeropageX, cycles[op as usize]),
         0x38 => Instruction(OpCode::SEC, AddressingMode::Implied, cycles[op as usize]),
         0x39 => Instruction(OpCode::AND, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0x3a => Instruction(OpCode::NOP, AddressingMode::Implied, cycles[op as usize]),
+        0x3b => Instruction(OpCode::RLA, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0x3c => Instruction(OpCode::NOP, AddressingMode::AbsoluteX, cycles[op as usize]),
         0x3d => Instruction(OpCode::AND, AddressingMode::AbsoluteX, cycles[op as usize]),
         0x3e => Instruction(OpCode::ROL, AddressingMode::AbsoluteX, cycles[op as usize]),
+        0x3f => Instruction(OpCode::RLA, AddressingMode::AbsoluteX, cycles[op as usize]),
 
         // 0x4X
         0x40 => Instruction(OpCode::RTI, AddressingMode::Implied, cycles[op as usize]),
         0x41 => Instruction(OpCode::EOR, AddressingMode::IndexedIndirect, cycles[op as usize]),
+        0x42 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0x43 => Instruction(OpCode::SRE, AddressingMode::IndexedIndirect, cycles[op as usize]),
+        0x44 => Instruction(OpCode::NOP, AddressingMode::Zeropage, cycles[op as usize]),
         0x45 => Instruction(OpCode::EOR, AddressingMode::Zeropage, cycles[op as usize]),
         0x46 => Instruction(OpCode::LSR, AddressingMode::Zeropage, cycles[op as usize]),
+        0x47 => Instruction(OpCode::SRE, AddressingMode::Zeropage, cycles[op as usize]),
         0x48 => Instruction(OpCode::PHA, AddressingMode::Implied, cycles[op as usize]),
         0x49 => Instruction(OpCode::EOR, AddressingMode::Immediate, cycles[op as usize]),
         0x4a => Instruction(OpCode::LSR, AddressingMode::Accumulator, cycles[op as usize]),
+        0x4b => Instruction(OpCode::ALR, AddressingMode::Immediate, cycles[op as usize]),
         0x4c => Instruction(OpCode::JMP, AddressingMode::Absolute, cycles[op as usize]),
         0x4d => Instruction(OpCode::EOR, AddressingMode::Absolute, cycles[op as usize]),
         0x4e => Instruction(OpCode::LSR, AddressingMode::Absolute, cycles[op as usize]),
+        0x4f => Instruction(OpCode::SRE, AddressingMode::Absolute, cycles[op as usize]),
 
         // 0x5X
         0x50 => Instruction(OpCode::BVC, AddressingMode::Relative, cycles[op as usize]),
         0x51 => Instruction(OpCode::EOR, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0x52 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0x53 => Instruction(OpCode::SRE, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0x54 => Instruction(OpCode::NOP, AddressingMode::ZeropageX, cycles[op as usize]),
         0x55 => Instruction(OpCode::EOR, AddressingMode::ZeropageX, cycles[op as usize]),
         0x56 => Instruction(OpCode::LSR, AddressingMode::ZeropageX, cycles[op as usize]),
+        0x57 => Instruction(OpCode::SRE, AddressingMode::ZeropageX, cycles[op as usize]),
         0x58 => Instruction(OpCode::CLI, AddressingMode::Implied, cycles[op as usize]),
         0x59 => Instruction(OpCode::EOR, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0x5a => Instruction(OpCode::NOP, AddressingMode::Implied, cycles[op as usize]),
+        0x5b => Instruction(OpCode::SRE, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0x5c => Instruction(OpCode::NOP, AddressingMode::AbsoluteX, cycles[op as usize]),
         0x5d => Instruction(OpCode::EOR, AddressingMode::AbsoluteX, cycles[op as usize]),
         0x5e => Instruction(OpCode::LSR, AddressingMode::AbsoluteX, cycles[op as usize]),
+        0x5f => Instruction(OpCode::SRE, AddressingMode::AbsoluteX, cycles[op as usize]),
 
         // 0x6X
         0x60 => Instruction(OpCode::RTS, AddressingMode::Implied, cycles[op as usize]),
         0x61 => Instruction(OpCode::ADC, AddressingMode::IndexedIndirect, cycles[op as usize]),
+        0x62 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0x63 => Instruction(OpCode::RRA, AddressingMode::IndexedIndirect, cycles[op as usize]),
+        0x64 => Instruction(OpCode::NOP, AddressingMode::Zeropage, cycles[op as usize]),
         0x65 => Instruction(OpCode::ADC, AddressingMode::Zeropage, cycles[op as usize]),
         0x66 => Instruction(OpCode::ROR, AddressingMode::Zeropage, cycles[op as usize]),
+        0x67 => Instruction(OpCode::RRA, AddressingMode::Zeropage, cycles[op as usize]),
         0x68 => Instruction(OpCode::PLA, AddressingMode::Implied, cycles[op as usize]),
         0x69 => Instruction(OpCode::ADC, AddressingMode::Immediate, cycles[op as usize]),
         0x6a => Instruction(OpCode::ROR, AddressingMode::Accumulator, cycles[op as usize]),
+        0x6b => Instruction(OpCode::ARR, AddressingMode::Immediate, cycles[op as usize]),
         0x6c => Instruction(OpCode::JMP, AddressingMode::AbsoluteIndirect, cycles[op as usize]),
         0x6d => Instruction(OpCode::ADC, AddressingMode::Absolute, cycles[op as usize]),
         0x6e => Instruction(OpCode::ROR, AddressingMode::Absolute, cycles[op as usize]),
+        0x6f => Instruction(OpCode::RRA, AddressingMode::Absolute, cycles[op as usize]),
 
         // 0x7X
         0x70 => Instruction(OpCode::BVS, AddressingMode::Relative, cycles[op as usize]),
         0x71 => Instruction(OpCode::ADC, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0x72 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0x73 => Instruction(OpCode::RRA, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0x74 => Instruction(OpCode::NOP, AddressingMode::ZeropageX, cycles[op as usize]),
         0x75 => Instruction(OpCode::ADC, AddressingMode::ZeropageX, cycles[op as usize]),
         0x76 => Instruction(OpCode::ROR, AddressingMode::ZeropageX, cycles[op as usize]),
+        0x77 => Instruction(OpCode::RRA, AddressingMode::ZeropageX, cycles[op as usize]),
         0x78 => Instruction(OpCode::SEI, AddressingMode::Implied, cycles[op as usize]),
         0x79 => Instruction(OpCode::ADC, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0x7a => Instruction(OpCode::NOP, AddressingMode::Implied, cycles[op as usize]),
+        0x7b => Instruction(OpCode::RRA, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0x7c => Instruction(OpCode::NOP, AddressingMode::AbsoluteX, cycles[op as usize]),
         0x7d => Instruction(OpCode::ADC, AddressingMode::AbsoluteX, cycles[op as usize]),
         0x7e => Instruction(OpCode::ROR, AddressingMode::AbsoluteX, cycles[op as usize]),
+        0x7f => Instruction(OpCode::RRA, AddressingMode::AbsoluteX, cycles[op as usize]),
 
         // 0x8X
+        0x80 => Instruction(OpCode::NOP, AddressingMode::Immediate, cycles[op as usize]),
         0x81 => Instruction(OpCode::STA, AddressingMode::IndexedIndirect, cycles[op as usize]),
+        0x82 => Instruction(OpCode::NOP, AddressingMode::Immediate, cycles[op as usize]),
+        0x83 => Instruction(OpCode::SAX, AddressingMode::IndexedIndirect, cycles[op as usize]),
         0x84 => Instruction(OpCode::STY, AddressingMode::Zeropage, cycles[op as usize]),
         0x85 => Instruction(OpCode::STA, AddressingMode::Zeropage, cycles[op as usize]),
         0x86 => Instruction(OpCode::STX, AddressingMode::Zeropage, cycles[op as usize]),
+        0x87 => Instruction(OpCode::SAX, AddressingMode::Zeropage, cycles[op as usize]),
         0x88 => Instruction(OpCode::DEY, AddressingMode::Implied, cycles[op as usize]),
+        0x89 => Instruction(OpCode::NOP, AddressingMode::Immediate, cycles[op as usize]),
         0x8a => Instruction(OpCode::TXA, AddressingMode::Implied, cycles[op as usize]),
+        0x8b => Instruction(OpCode::XAA, AddressingMode::Immediate, cycles[op as usize]),
         0x8c => Instruction(OpCode::STY, AddressingMode::Absolute, cycles[op as usize]),
         0x8d => Instruction(OpCode::STA, AddressingMode::Absolute, cycles[op as usize]),
         0x8e => Instruction(OpCode::STX, AddressingMode::Absolute, cycles[op as usize]),
+        0x8f => Instruction(OpCode::SAX, AddressingMode::Absolute, cycles[op as usize]),
 
         // 0x9X
         0x90 => Instruction(OpCode::BCC, AddressingMode::Relative, cycles[op as usize]),
         0x91 => Instruction(OpCode::STA, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0x92 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0x93 => Instruction(OpCode::AHX, AddressingMode::IndirectIndexed, cycles[op as usize]),
         0x94 => Instruction(OpCode::STY, AddressingMode::ZeropageX, cycles[op as usize]),
         0x95 => Instruction(OpCode::STA, AddressingMode::ZeropageX, cycles[op as usize]),
         0x96 => Instruction(OpCode::STX, AddressingMode::ZeropageY, cycles[op as usize]),
+        0x97 => Instruction(OpCode::SAX, AddressingMode::ZeropageY, cycles[op as usize]),
         0x98 => Instruction(OpCode::TYA, AddressingMode::Implied, cycles[op as usize]),
         0x99 => Instruction(OpCode::STA, AddressingMode::AbsoluteY, cycles[op as usize]),
         0x9a => Instruction(OpCode::TXS, AddressingMode::Implied, cycles[op as usize]),
+        0x9b => Instruction(OpCode::TAS, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0x9c => Instruction(OpCode::SHY, AddressingMode::AbsoluteX, cycles[op as usize]),
         0x9d => Instruction(OpCode::STA, AddressingMode::AbsoluteX, cycles[op as usize]),
+        0x9e => Instruction(OpCode::SHX, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0x9f => Instruction(OpCode::AHX, AddressingMode::AbsoluteY, cycles[op as usize]),
 
         // 0xaX
         0xa0 => Instruction(OpCode::LDY, AddressingMode::Immediate, cycles[op as usize]),
         0xa1 => Instruction(OpCode::LDA, AddressingMode::IndexedIndirect, cycles[op as usize]),
         0xa2 => Instruction(OpCode::LDX, AddressingMode::Immediate, cycles[op as usize]),
+        0xa3 => Instruction(OpCode::LAX, AddressingMode::IndexedIndirect, cycles[op as usize]),
         0xa4 => Instruction(OpCode::LDY, AddressingMode::Zeropage, cycles[op as usize]),
         0xa5 => Instruction(OpCode::LDA, AddressingMode::Zeropage, cycles[op as usize]),
         0xa6 => Instruction(OpCode::LDX, AddressingMode::Zeropage, cycles[op as usize]),
+        0xa7 => Instruction(OpCode::LAX, AddressingMode::Zeropage, cycles[op as usize]),
         0xa8 => Instruction(OpCode::TAY, AddressingMode::Implied, cycles[op as usize]),
         0xa9 => Instruction(OpCode::LDA, AddressingMode::Immediate, cycles[op as usize]),
         0xaa => Instruction(OpCode::TAX, AddressingMode::Implied, cycles[op as usize]),
+        0xab => Instruction(OpCode::LXA, AddressingMode::Immediate, cycles[op as usize]),
         0xac => Instruction(OpCode::LDY, AddressingMode::Absolute, cycles[op as usize]),
         0xad => Instruction(OpCode::LDA, AddressingMode::Absolute, cycles[op as usize]),
         0xae => Instruction(OpCode::LDX, AddressingMode::Absolute, cycles[op as usize]),
+        0xaf => Instruction(OpCode::LAX, AddressingMode::Absolute, cycles[op as usize]),
 
         // 0xbX
         0xb0 => Instruction(OpCode::BCS, AddressingMode::Relative, cycles[op as usize]),
         0xb1 => Instruction(OpCode::LDA, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0xb2 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0xb3 => Instruction(OpCode::LAX, AddressingMode::IndirectIndexed, cycles[op as usize]),
         0xb4 => Instruction(OpCode::LDY, AddressingMode::ZeropageX, cycles[op as usize]),
         0xb5 => Instruction(OpCode::LDA, AddressingMode::ZeropageX, cycles[op as usize]),
         0xb6 => Instruction(OpCode::LDX, AddressingMode::ZeropageY, cycles[op as usize]),
+        0xb7 => Instruction(OpCode::LAX, AddressingMode::ZeropageY, cycles[op as usize]),
         0xb8 => Instruction(OpCode::CLV, AddressingMode::Implied, cycles[op as usize]),
         0xb9 => Instruction(OpCode::LDA, AddressingMode::AbsoluteY, cycles[op as usize]),
         0xba => Instruction(OpCode::TSX, AddressingMode::Implied, cycles[op as usize]),
+        0xbb => Instruction(OpCode::LAS, AddressingMode::AbsoluteY, cycles[op as usize]),
         0xbc => Instruction(OpCode::LDY, AddressingMode::AbsoluteX, cycles[op as usize]),
         0xbd => Instruction(OpCode::LDA, AddressingMode::AbsoluteX, cycles[op as usize]),
         0xbe => Instruction(OpCode::LDX, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0xbf => Instruction(OpCode::LAX, AddressingMode::AbsoluteY, cycles[op as usize]),
 
         // 0xcX
         0xc0 => Instruction(OpCode::CPY, AddressingMode::Immediate, cycles[op as usize]),
         0xc1 => Instruction(OpCode::CMP, AddressingMode::IndexedIndirect, cycles[op as usize]),
+        0xc2 => Instruction(OpCode::NOP, AddressingMode::Immediate, cycles[op as usize]),
+        0xc3 => Instruction(OpCode::DCP, AddressingMode::IndexedIndirect, cycles[op as usize]),
         0xc4 => Instruction(OpCode::CPY, AddressingMode::Zeropage, cycles[op as usize]),
         0xc5 => Instruction(OpCode::CMP, AddressingMode::Zeropage, cycles[op as usize]),
         0xc6 => Instruction(OpCode::DEC, AddressingMode::Zeropage, cycles[op as usize]),
+        0xc7 => Instruction(OpCode::DCP, AddressingMode::Zeropage, cycles[op as usize]),
         0xc8 => Instruction(OpCode::INY, AddressingMode::Implied, cycles[op as usize]),
         0xc9 => Instruction(OpCode::CMP, AddressingMode::Immediate, cycles[op as usize]),
         0xca => Instruction(OpCode::DEX, AddressingMode::Implied, cycles[op as usize]),
+        0xcb => Instruction(OpCode::AXS, AddressingMode::Immediate, cycles[op as usize]),
         0xcc => Instruction(OpCode::CPY, AddressingMode::Absolute, cycles[op as usize]),
         0xcd => Instruction(OpCode::CMP, AddressingMode::Absolute, cycles[op as usize]),
         0xce => Instruction(OpCode::DEC, AddressingMode::Absolute, cycles[op as usize]),
+        0xcf => Instruction(OpCode::DCP, AddressingMode::Absolute, cycles[op as usize]),
 
         // 0xdX
         0xd0 => Instruction(OpCode::BNE, AddressingMode::Relative, cycles[op as usize]),
         0xd1 => Instruction(OpCode::CMP, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0xd2 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0xd3 => Instruction(OpCode::DCP, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0xd4 => Instruction(OpCode::NOP, AddressingMode::ZeropageX, cycles[op as usize]),
         0xd5 => Instruction(OpCode::CMP, AddressingMode::ZeropageX, cycles[op as usize]),
         0xd6 => Instruction(OpCode::DEC, AddressingMode::ZeropageX, cycles[op as usize]),
+        0xd7 => Instruction(OpCode::DCP, AddressingMode::ZeropageX, cycles[op as usize]),
         0xd8 => Instruction(OpCode::CLD, AddressingMode::Implied, cycles[op as usize]),
         0xd9 => Instruction(OpCode::CMP, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0xda => Instruction(OpCode::NOP, AddressingMode::Implied, cycles[op as usize]),
+        0xdb => Instruction(OpCode::DCP, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0xdc => Instruction(OpCode::NOP, AddressingMode::AbsoluteX, cycles[op as usize]),
         0xdd => Instruction(OpCode::CMP, AddressingMode::AbsoluteX, cycles[op as usize]),
         0xde => Instruction(OpCode::DEC, AddressingMode::AbsoluteX, cycles[op as usize]),
+        0xdf => Instruction(OpCode::DCP, AddressingMode::AbsoluteX, cycles[op as usize]),
 
         // 0xeX
         0xe0 => Instruction(OpCode::CPX, AddressingMode::Immediate, cycles[op as usize]),
         0xe1 => Instruction(OpCode::SBC, AddressingMode::IndexedIndirect, cycles[op as usize]),
+        0xe2 => Instruction(OpCode::NOP, AddressingMode::Immediate, cycles[op as usize]),
+        0xe3 => Instruction(OpCode::ISC, AddressingMode::IndexedIndirect, cycles[op as usize]),
         0xe4 => Instruction(OpCode::CPX, AddressingMode::Zeropage, cycles[op as usize]),
         0xe5 => Instruction(OpCode::SBC, AddressingMode::Zeropage, cycles[op as usize]),
         0xe6 => Instruction(OpCode::INC , AddressingMode::Zeropage, cycles[op as usize]),
+        0xe7 => Instruction(OpCode::ISC, AddressingMode::Zeropage, cycles[op as usize]),
         0xe8 => Instruction(OpCode::INX, AddressingMode::Implied, cycles[op as usize]),
         0xe9 => Instruction(OpCode::SBC, AddressingMode::Immediate, cycles[op as usize]),
         0xea => Instruction(OpCode::NOP, AddressingMode::Implied, cycles[op as usize]),
+        0xeb => Instruction(OpCode::SBC, AddressingMode::Immediate, cycles[op as usize]),
         0xec => Instruction(OpCode::CPX, AddressingMode::Absolute, cycles[op as usize]),
         0xed => Instruction(OpCode::SBC, AddressingMode::Absolute, cycles[op as usize]),
         0xee => Instruction(OpCode::INC, AddressingMode::Absolute, cycles[op as usize]),
+        0xef => Instruction(OpCode::ISC, AddressingMode::Absolute, cycles[op as usize]),
 
         // 0xfX
         0xf0 => Instruction(OpCode::BEQ, AddressingMode::Relative, cycles[op as usize]),
         0xf1 => Instruction(OpCode::SBC, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0xf2 => Instruction(OpCode::KIL, AddressingMode::Implied, cycles[op as usize]),
+        0xf3 => Instruction(OpCode::ISC, AddressingMode::IndirectIndexed, cycles[op as usize]),
+        0xf4 => Instruction(OpCode::NOP, AddressingMode::ZeropageX, cycles[op as usize]),
         0xf5 => Instruction(OpCode::SBC, AddressingMode::ZeropageX, cycles[op as usize]),
         0xf6 => Instruction(OpCode::INC, AddressingMode::ZeropageX, cycles[op as usize]),
+        0xf7 => Instruction(OpCode::ISC, AddressingMode::ZeropageX, cycles[op as usize]),
         0xf8 => Instruction(OpCode::SED, AddressingMode::Implied, cycles[op as usize]),
         0xf9 => Instruction(OpCode::SBC, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0xfa => Instruction(OpCode::NOP, AddressingMode::Implied, cycles[op as usize]),
+        0xfb => Instruction(OpCode::ISC, AddressingMode::AbsoluteY, cycles[op as usize]),
+        0xfc => Instruction(OpCode::NOP, AddressingMode::AbsoluteX, cycles[op as usize]),
         0xfd => Instruction(OpCode::SBC, AddressingMode::AbsoluteX, cycles[op as usize]),
         0xfe => Instruction(OpCode::INC, AddressingMode::AbsoluteX, cycles[op as usize]),
-
-        _ => panic!("not found op: {:x}", op),
+        0xff => Instruction(OpCode::ISC, AddressingMode::AbsoluteX, cycles[op as usize]),
     }
 }
\ No newline at end of file
@@ -1,10 +1,337 @@
+use super::*;
+use crate::{apu, cartridge, input, mapper, ppu, screen, wram};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// $8000 から prog が入った NROM-128 で CPU を作る
+fn cpu(prog: &[u8]) -> Cpu {
+    let mut rom = vec![0xea; cartridge::PROGRAM_ROM_SIZE];
+    rom[..prog.len()].copy_from_slice(prog);
+    cpu_with_rom(0x00, rom)
+}
+
+/// flags6 のマッパーで PRG ROM が prg_rom の CPU を作る
+fn cpu_with_rom(flags6: u8, prg_rom: Vec<u8>) -> Cpu {
+    let pages = (prg_rom.len() / cartridge::PROGRAM_ROM_SIZE) as u8;
+    let mut binary = cartridge::ines_header([pages, 1, flags6, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    binary.extend(prg_rom);
+    binary.extend(vec![0; cartridge::CHARACTER_ROM_SIZE]);
+    let cartridge = cartridge::Cartridge::parse(&binary).unwrap();
+
+    let mapper = Rc::new(RefCell::new(mapper::new(cartridge).unwrap()));
+    let wram = wram::WRAM::new();
+    let ppu = ppu::Ppu::new(screen::Screen::new(), mapper.clone());
+    let apu = apu::Apu::new();
+    let input = input::ControllerPorts::new();
+    let cpu_bus = cpu_bus::CpuBus::new(
+        wram,
+        Rc::new(RefCell::new(ppu)),
+        Rc::new(RefCell::new(apu)),
+        Rc::new(RefCell::new(input)),
+        mapper,
+    );
+    Cpu::new(cpu_bus)
+}
+
 #[test]
 fn it_works() {
-    use super::*;
-    use crate::{cpu_bus, wram, ppu};
-    let wram = wram::WRAM::new();
-    let ppu = ppu::Ppu::new();
-    let mut cpu_bus = cpu_bus::CpuBus::new(wram, ppu, vec![]);
-    let mut cpu = Cpu::new();
-    cpu.run(&mut cpu_bus);
-}
\ No newline at end of file
+    // $8000: LDA #$42
+    let mut cpu = cpu(&[0xa9, 0x42]);
+    assert_eq!(cpu.run(), 2);
+    assert_eq!(cpu.register.A, 0x42);
+}
+
+#[test]
+fn oam_dma() {
+    // LDA #$02, STA $4014, STA $4014
+    let mut cpu = cpu(&[0xa9, 0x02, 0x8d, 0x14, 0x40, 0x8d, 0x14, 0x40]);
+    for i in 0..0x100 {
+        cpu.cpu_bus.write(0x0200 + i, i as u8);
+    }
+    assert_eq!(cpu.run(), 2);
+    // 2 + 4 サイクル目で終わるので偶数
+    assert_eq!(cpu.run(), 4 + 513);
+    // 519 + 4 サイクル目で終わるので奇数
+    assert_eq!(cpu.run(), 4 + 514);
+
+    let mut ppu = cpu.cpu_bus.ppu.borrow_mut();
+    ppu.write_register(0x2003, 0x10);
+    assert_eq!(ppu.read_register(0x2004), 0x10);
+}
+
+#[test]
+fn nmi_on_vblank() {
+    // LDA #$80, STA $2000, JMP $8005
+    let mut prog = vec![0xea; 0x4000];
+    prog[..8].copy_from_slice(&[0xa9, 0x80, 0x8d, 0x00, 0x20, 0x4c, 0x05, 0x80]);
+    // NMI ベクタ $FFFA -> $9000
+    prog[0x3ffa] = 0x00;
+    prog[0x3ffb] = 0x90;
+    let mut cpu = cpu(&prog);
+
+    let mut cycles = 0;
+    while cpu.register.PC < 0x9000 {
+        cycles += cpu.run() as usize;
+        assert!(cycles < 30000);
+    }
+    // VBLANK は 241 ライン目の dot 1 で立つ
+    assert!(cycles * 3 > 241 * 341);
+    assert!(cpu.cpu_bus.ppu.borrow().nmi());
+}
+
+#[test]
+fn nmi_while_polling_vblank() {
+    // LDA #$80, STA $2000, NOP, LDA $2002 を並べて JMP $8006
+    // VBLANK が立った命令の中で $2002 を読んで下ろしても NMI は起きる
+    let mut prog = vec![0xea; 0x4000];
+    prog[..6].copy_from_slice(&[0xa9, 0x80, 0x8d, 0x00, 0x20, 0xea]);
+    for i in 0..100 {
+        prog[6 + i * 3..9 + i * 3].copy_from_slice(&[0xad, 0x02, 0x20]);
+    }
+    prog[306..309].copy_from_slice(&[0x4c, 0x06, 0x80]);
+    prog[0x3ffa] = 0x00;
+    prog[0x3ffb] = 0x90;
+    let mut cpu = cpu(&prog);
+
+    let mut cycles = 0;
+    while cpu.register.PC < 0x9000 {
+        cycles += cpu.run() as usize;
+        assert!(cycles * 3 < 242 * 341);
+    }
+    // フラグは読んで下ろしてある
+    assert!(!cpu.cpu_bus.ppu.borrow().nmi());
+}
+
+#[test]
+fn apu_frame_irq() {
+    // CLI, JMP $8001
+    let mut prog = vec![0xea; 0x4000];
+    prog[..4].copy_from_slice(&[0x58, 0x4c, 0x01, 0x80]);
+    // IRQ ベクタ $FFFE -> $9000
+    prog[0x3ffe] = 0x00;
+    prog[0x3fff] = 0x90;
+    let mut cpu = cpu(&prog);
+
+    let mut cycles = 0;
+    while cpu.register.PC < 0x9000 {
+        cycles += cpu.run() as usize;
+        assert!(cycles < 40000);
+    }
+    // 4 ステップモードのフレームカウンタは 29828 サイクル目で IRQ を出す
+    assert!(cycles >= 29828);
+    assert_eq!(cpu.cpu_bus.read(0x4015) & 0x40, 0x40);
+    assert!(!cpu.cpu_bus.irq());
+}
+
+#[test]
+fn dmc_dma_stall() {
+    // LDA #$0F, STA $4010, LDA #$10, STA $4015
+    let mut cpu = cpu(&[0xa9, 0x0f, 0x8d, 0x10, 0x40, 0xa9, 0x10, 0x8d, 0x15, 0x40]);
+    assert_eq!(cpu.run(), 2);
+    assert_eq!(cpu.run(), 4);
+    assert_eq!(cpu.run(), 2);
+    // DMC を有効にするとすぐに 1 byte 目を読みに行って 4 サイクル止まる
+    assert_eq!(cpu.run(), 4 + 4);
+    // 1 byte (8 bit) 読み終わるまで NOP が続く
+    assert_eq!(cpu.run(), 2);
+}
+
+#[test]
+fn joypad() {
+    // LDA #$01, STA $4016, LDA #$00, STA $4016, LDA $4016, LDA $4016
+    let mut cpu = cpu(&[
+        0xa9, 0x01, 0x8d, 0x16, 0x40, 0xa9, 0x00, 0x8d, 0x16, 0x40, 0xad, 0x16, 0x40, 0xad, 0x16, 0x40,
+    ]);
+    cpu.cpu_bus.input.borrow_mut().set_buttons(0, input::ButtonState::from_bits(0b10));
+    for _ in 0..5 {
+        cpu.run();
+    }
+    // 上位 3bit は open bus (アドレスの上位バイト $40)
+    assert_eq!(cpu.register.A, 0x40);
+    cpu.run();
+    assert_eq!(cpu.register.A, 0x41);
+}
+
+#[test]
+fn expansion_keyboard() {
+    // LDA #$05, STA $4016, LDA #$04, STA $4016, LDA $4017
+    let mut cpu = cpu(&[0xa9, 0x05, 0x8d, 0x16, 0x40, 0xa9, 0x04, 0x8d, 0x16, 0x40, 0xad, 0x17, 0x40]);
+    let mut state = input::KeyboardState::default();
+    // ]
+    state.keys[0][0] = true;
+    let mut keyboard = input::Keyboard::new();
+    keyboard.set_state(state);
+    cpu.cpu_bus.input.borrow_mut().connect_expansion(Some(Box::new(keyboard)));
+    for _ in 0..5 {
+        cpu.run();
+    }
+    // D0 は 2P のコントローラ (何も押していない), D1-D4 がキーボード
+    assert_eq!(cpu.register.A, 0x40 | 0x1c);
+}
+
+#[test]
+fn decode_all_opcodes() {
+    // 未定義の命令はないので全部デコードできる
+    for op in 0..=0xff {
+        op::decode_op(op);
+    }
+}
+
+#[test]
+fn unofficial_load_store() {
+    // LDA #$f0, LDX #$3c, SAX $10, LAX $10, LAX ($20),Y (Y=$ff), NOP $1234,X
+    let mut cpu = cpu(&[
+        0xa9, 0xf0, 0xa2, 0x3c, 0x87, 0x10, 0xa7, 0x10, 0xb3, 0x20, 0x3c, 0x34, 0x12,
+    ]);
+    cpu.register.Y = 0xff;
+    cpu.cpu_bus.write(0x0020, 0x01);
+    cpu.cpu_bus.write(0x0021, 0x03);
+    cpu.cpu_bus.write(0x0400, 0x80);
+    cpu.run();
+    cpu.run();
+    // SAX はフラグを変えない
+    assert_eq!(cpu.run(), 3);
+    assert_eq!(cpu.cpu_bus.read(0x0010), 0x30);
+    assert!(!cpu.register.P.zero);
+    assert_eq!(cpu.run(), 3);
+    assert_eq!((cpu.register.A, cpu.register.X), (0x30, 0x30));
+    // $0301 + $ff でページをまたぐので 1 サイクル増える
+    assert_eq!(cpu.run(), 5 + 1);
+    assert_eq!((cpu.register.A, cpu.register.X), (0x80, 0x80));
+    assert!(cpu.register.P.negative);
+    // NOP abs,X もページをまたがなければ 4 サイクル
+    assert_eq!(cpu.run(), 4);
+    assert_eq!(cpu.register.PC, 0x800d);
+}
+
+#[test]
+fn page_cross_register_read_timing() {
+    // LDX #$03, LDA $1FFF,X ($2002)
+    let mut cpu = cpu(&[0xa2, 0x03, 0xbd, 0xff, 0x1f]);
+    // LDX の後, VBLANK が立つ (241, 1) の 10 ドット前になるようにしておく
+    cpu.cpu_bus.ppu.borrow_mut().run(341 * 241 + 1 - 10 - 6);
+    cpu.run();
+    // 読むのは 5 サイクル目なので 12 ドット進んだ後, もう立っている
+    assert_eq!(cpu.run(), 4 + 1);
+    assert_eq!(cpu.register.A & 0x80, 0x80);
+}
+
+#[test]
+fn unofficial_read_modify_write() {
+    // SEC, SLO $10, RLA $11, SRE $12, RRA $13, DCP $14, ISC $15
+    let mut cpu = cpu(&[
+        0x38, 0x07, 0x10, 0x27, 0x11, 0x47, 0x12, 0x67, 0x13, 0xc7, 0x14, 0xe7, 0x15,
+    ]);
+    for (addr, data) in [0x81, 0xc3, 0x03, 0x02, 0x43, 0xff].iter().enumerate() {
+        cpu.cpu_bus.write(0x10 + addr as u16, *data);
+    }
+    cpu.run();
+
+    // $81 << 1 = $02, A = $00 | $02, C = 1
+    assert_eq!(cpu.run(), 5);
+    assert_eq!(cpu.cpu_bus.read(0x10), 0x02);
+    assert_eq!(cpu.register.A, 0x02);
+    assert!(cpu.register.P.carry);
+
+    // $c3 を C = 1 で左に回すと $87, A = $02 & $87
+    cpu.run();
+    assert_eq!(cpu.cpu_bus.read(0x11), 0x87);
+    assert_eq!(cpu.register.A, 0x02);
+    assert!(cpu.register.P.carry);
+
+    // $03 >> 1 = $01, A = $02 ^ $01
+    cpu.run();
+    assert_eq!(cpu.cpu_bus.read(0x12), 0x01);
+    assert_eq!(cpu.register.A, 0x03);
+    assert!(cpu.register.P.carry);
+
+    // $02 を C = 1 で右に回すと $81, A = $03 + $81 + 0
+    cpu.run();
+    assert_eq!(cpu.cpu_bus.read(0x13), 0x81);
+    assert_eq!(cpu.register.A, 0x84);
+    assert!(!cpu.register.P.carry);
+    assert!(cpu.register.P.negative);
+
+    // $43 - 1 = $42 と A = $84 を比べる
+    cpu.run();
+    assert_eq!(cpu.cpu_bus.read(0x14), 0x42);
+    assert!(cpu.register.P.carry);
+    assert!(!cpu.register.P.zero);
+
+    // $ff + 1 = $00, A = $84 - $00
+    assert_eq!(cpu.run(), 5);
+    assert_eq!(cpu.cpu_bus.read(0x15), 0x00);
+    assert_eq!(cpu.register.A, 0x84);
+    assert!(cpu.register.P.carry);
+}
+
+#[test]
+fn unofficial_immediate() {
+    // LDA #$ff, ANC #$81, ALR #$03, LDA #$ff, SEC, ARR #$c0, LDX #$0f, AXS #$10
+    let mut cpu = cpu(&[
+        0xa9, 0xff, 0x0b, 0x81, 0x4b, 0x03, 0xa9, 0xff, 0x38, 0x6b, 0xc0, 0xa2, 0x0f, 0xcb, 0x10,
+    ]);
+    cpu.run();
+    cpu.run();
+    assert_eq!(cpu.register.A, 0x81);
+    assert!(cpu.register.P.carry && cpu.register.P.negative);
+
+    cpu.run();
+    assert_eq!(cpu.register.A, 0x00);
+    assert!(cpu.register.P.carry && cpu.register.P.zero);
+
+    cpu.run();
+    cpu.run();
+    // ($ff & $c0) を C = 1 で右に回すと $e0, C = bit 6, V = bit 6 ^ bit 5
+    cpu.run();
+    assert_eq!(cpu.register.A, 0xe0);
+    assert!(cpu.register.P.carry);
+    assert!(!cpu.register.P.overflow);
+
+    // X = ($e0 & $0f) - $10, borrow が出ると C = 0
+    cpu.run();
+    cpu.run();
+    assert_eq!(cpu.register.X, 0xf0);
+    assert!(!cpu.register.P.carry && cpu.register.P.negative);
+}
+
+#[test]
+fn kil_jams_cpu() {
+    // LDA #$80, STA $2000 (NMI 有効), KIL
+    let mut cpu = cpu(&[0xa9, 0x80, 0x8d, 0x00, 0x20, 0x02]);
+    let mut cycles = 0;
+    while cycles < 30000 {
+        cycles += cpu.run() as usize;
+    }
+    // NMI が来ても止まったまま
+    assert_eq!(cpu.register.PC, 0x8005);
+    assert!(cpu.jammed);
+
+    cpu.reset();
+    assert!(!cpu.jammed);
+}
+
+#[test]
+fn mmc1_ignores_rmw_second_write() {
+    // MMC1, PRG ROM 32 KiB
+    // INC $FFFF, (LDA #v, STA $8000) x 5, LDA $C000
+    let mut prog = vec![0xee, 0xff, 0xff];
+    for v in [0, 1, 0, 1, 0].iter() {
+        prog.extend(&[0xa9, *v, 0x8d, 0x00, 0x80]);
+    }
+    prog.extend(&[0xad, 0x00, 0xc0]);
+    let mut rom = vec![0xea; 2 * cartridge::PROGRAM_ROM_SIZE];
+    rom[..prog.len()].copy_from_slice(&prog);
+    for data in rom[cartridge::PROGRAM_ROM_SIZE..].iter_mut() {
+        *data = 0x00;
+    }
+    // INC は $7F を書き戻してから $80 (リセット) を書くが, 2 回目は無視される
+    *rom.last_mut().unwrap() = 0x7f;
+    let mut cpu = cpu_with_rom(0x10, rom);
+    for _ in 0..12 {
+        cpu.run();
+    }
+    // シフトレジスタに残った 1 と続く 4 回で control = %10101 (32 KiB モード)
+    // $C000 は 2 つ目のバンク
+    assert_eq!(cpu.register.A, 0x00);
+}
@@ -1,3 +1,6 @@
+use crate::apu;
+use crate::input;
+use crate::mapper;
 use crate::ppu;
 use crate::wram;
 use std::cell::RefCell;
@@ -7,29 +10,126 @@ use std::rc::Rc;
 pub struct CpuBus {
     wram: wram::WRAM,
     pub ppu: Rc<RefCell<ppu::Ppu>>,
-    // extend_ram
-    // extend_rom
-    prog_rom1: Vec<u8>,
-    // prog_rom2
+    pub apu: Rc<RefCell<apu::Apu>>,
+    /// $4016, $4017 のコントローラポート
+    pub input: Rc<RefCell<input::ControllerPorts>>,
+    /// extend_ram, battery backup RAM, program ROM はカートリッジ側
+    mapper: mapper::SharedMapper,
+    /// 最後にデータバスに乗った値
+    /// 何もつながっていないアドレスを読むとこれが返る
+    open_bus: u8,
+    /// $4014 に書き込まれて OAM DMA が走ったか
+    /// CPU が止まるサイクル数は Cpu::run で数える
+    oam_dma: bool,
+    /// 今の命令で PPU, APU のレジスタを読み書きするのが何サイクル目か
+    access_cycle: u16,
+    /// 今の命令の中で PPU, APU を何サイクル分進めたか
+    synced: u16,
+    /// 今の命令の中で DMC の DMA で CPU が止まったサイクル数
+    dmc_stall: u16,
+    /// 今の命令が始まるまでの電源投入からのサイクル数
+    cycles: u64,
     // pro: u8,
-    // apu: u8,
-    // keypad: u8,
-    // dma: u8,
 }
 
 impl CpuBus {
-    pub fn new(wram: wram::WRAM, ppu: Rc<RefCell<ppu::Ppu>>, prog: Vec<u8>) -> Self {
+    pub fn new(
+        wram: wram::WRAM,
+        ppu: Rc<RefCell<ppu::Ppu>>,
+        apu: Rc<RefCell<apu::Apu>>,
+        input: Rc<RefCell<input::ControllerPorts>>,
+        mapper: mapper::SharedMapper,
+    ) -> Self {
         CpuBus {
             wram,
             // pro,
             ppu,
-            // apu, keypad, dma
-            prog_rom1: prog,
+            apu,
+            input,
+            mapper,
+            open_bus: 0,
+            oam_dma: false,
+            access_cycle: 0,
+            synced: 0,
+            dmc_stall: 0,
+            cycles: 0,
         }
     }
+    /// IRQ 線の状態
+    /// カートリッジや APU のフレームカウンタがレベルトリガで引き下げる
+    pub fn irq(&self) -> bool {
+        self.mapper.borrow().irq() || self.apu.borrow().irq()
+    }
+
+    /// NMI 線の立ち上がりがあったか
+    /// 命令の途中で立ち上がっても PPU が覚えているので取りこぼさない
+    pub fn take_nmi(&mut self) -> bool {
+        self.ppu.borrow_mut().take_nmi()
+    }
+
+    /// 命令の実行前に呼ぶ
+    /// cycles は割り込みと命令のサイクル数の合計で, PPU, APU のレジスタへのアクセスは最後のサイクルとみなす
+    pub fn begin_instruction(&mut self, cycles: u16) {
+        self.access_cycle = cycles.saturating_sub(1);
+        self.synced = 0;
+        self.dmc_stall = 0;
+    }
+
+    /// 命令の実行後に呼ぶ, 残りのサイクル分 PPU と APU を進める
+    /// # Return
+    /// DMC の DMA で CPU が止まったサイクル数
+    pub fn end_instruction(&mut self, cycles: u16) -> u16 {
+        self.sync(cycles);
+        self.cycles += (cycles + self.dmc_stall) as u64;
+        self.dmc_stall
+    }
+
+    /// PPU と APU を今の命令の cycles サイクル目まで進める
+    /// $2002 を読むタイミングで VBLANK フラグが, $4015 でフレーム割り込みが変わるので
+    /// レジスタに触る前に追いつかせる
+    /// DMC の DMA で止まった分だけ後ろにずれる
+    fn sync(&mut self, cycles: u16) {
+        while cycles + self.dmc_stall > self.synced {
+            let elapsed = (cycles + self.dmc_stall - self.synced) as usize;
+            let elapsed = self.apu.borrow_mut().run(elapsed);
+            self.ppu.borrow_mut().run(3 * elapsed);
+            self.synced += elapsed as u16;
+            self.run_dmc_dma();
+        }
+    }
+
+    /// DMC がサンプルを必要としていたら読んで渡す
+    /// CPU は 4 サイクル止まる
+    fn run_dmc_dma(&mut self) {
+        let addr = match self.apu.borrow().dma_address() {
+            Some(addr) => addr,
+            None => return,
+        };
+        // サンプルは $8000-$FFFF にしか置けないのでカートリッジから読む
+        let data = self.mapper.borrow_mut().cpu_read(addr).unwrap_or(self.open_bus);
+        self.open_bus = data;
+        self.apu.borrow_mut().fill_dmc(data);
+        self.dmc_stall += 4;
+    }
+
+    /// OAM DMA が走っていたら true を返してフラグを下ろす
+    pub fn take_oam_dma(&mut self) -> bool {
+        std::mem::replace(&mut self.oam_dma, false)
+    }
+
+    /// $XX00-$XXFF の 256 byte を順に $2004 (OAMDATA) に書き込む
+    fn run_oam_dma(&mut self, page: u8) {
+        let head = (page as u16) << 8;
+        for i in 0..0x100 {
+            let data = self.read(head | i);
+            self.ppu.borrow_mut().write_register(0x2004, data);
+        }
+        self.oam_dma = true;
+    }
+
     /// cpuのメモリマップから値を読み込む
-    pub fn read(&self, addr: u16) -> u8 {
-        match addr {
+    pub fn read(&mut self, addr: u16) -> u8 {
+        let data = match addr {
             // WRAM
             0x0000..=0x07ff => self.wram[addr as usize],
             // WRAM mirror
@@ -38,20 +138,29 @@ impl CpuBus {
             }
             // I/O port Ppu
             addr @ 0x2000..=0x3fff => {
+                self.sync(self.access_cycle);
                 let mut ppu = self.ppu.borrow_mut();
 
                 ppu.read_register((addr % 8) + 0x2000)
             }
-            // I/O port APU, etc
-            0x4000..=0x401f => unimplemented!("I/O port APU, etc"),
-            // extended RAM
-            0x4020..=0x5fff => unimplemented!("extended RAM"),
-            // battely backup RAM
-            0x6000..=0x7fff => unimplemented!("battely backup RAM"),
-            // PRG ROM LOW & HIGH
-            0x8000..=0xffff => self.prog_rom1[(addr - 0x8000) as usize],
-            // FIXME: ホントはHIGHとLOWに別れてるので変かもしれない
-        }
+            // APU のステータス, bit 5 は open bus
+            0x4015 => {
+                self.sync(self.access_cycle);
+                self.apu.borrow_mut().read_status() | (self.open_bus & 0x20)
+            }
+            // コントローラ, D0-D4 以外は open bus
+            // Zapper はビームの位置を見るので PPU を追いつかせる
+            0x4016 | 0x4017 => {
+                self.sync(self.access_cycle);
+                let port = (addr - 0x4016) as usize;
+                self.input.borrow_mut().read(port) | (self.open_bus & 0xe0)
+            }
+            0x4000..=0x401f => self.open_bus,
+            // extended RAM, battely backup RAM, PRG ROM LOW & HIGH
+            0x4020..=0xffff => self.mapper.borrow_mut().cpu_read(addr).unwrap_or(self.open_bus),
+        };
+        self.open_bus = data;
+        data
     }
 
     /// write_by_cpuはWRAMにデータを書き込む
@@ -59,6 +168,7 @@ impl CpuBus {
     /// * `書き込んだ結果の値`
     pub fn write(&mut self, addr: u16, data: u8) -> u8 {
         // println!("cpu:write addr: {:x}, data: {:x}", addr, data);
+        self.open_bus = data;
 
         match addr {
             // WRAM
@@ -73,19 +183,35 @@ impl CpuBus {
             }
             // I/O port Ppu
             addr@0x2000..=0x3fff => {
+                self.sync(self.access_cycle);
                 let mut ppu = self.ppu.borrow_mut();
                 ppu.write_register((addr % 8) + 0x2000, data)
             }
-            // I/O port APU, etc
-            0x4000..=0x401f => unimplemented!("I/O port APU, etc"),
-            // extended RAM
-            0x4020..=0x5fff => unimplemented!("extended RAM"),
-            // battely backup RAM
-            0x6000..=0x7fff => unimplemented!("battely backup RAM"),
-            // PRG ROM LOW
-            0x8000..=0xbfff => panic!("ROM is readonly"),
-            // PRG ROM HIGH
-            0xc000..=0xffff => panic!("ROM is readonly"),
+            // OAM DMA
+            0x4014 => {
+                self.run_oam_dma(data);
+                data
+            }
+            // コントローラのストローブ
+            0x4016 => {
+                self.input.borrow_mut().write(data);
+                data
+            }
+            // I/O port APU
+            0x4000..=0x4013 | 0x4015 | 0x4017 => {
+                self.sync(self.access_cycle);
+                self.apu.borrow_mut().write_register(addr, data);
+                data
+            }
+            0x4000..=0x401f => data,
+            // extended RAM, battely backup RAM, PRG ROM LOW & HIGH
+            // ROM への書き込みはマッパーのレジスタになる
+            0x4020..=0xffff => {
+                let mut mapper = self.mapper.borrow_mut();
+                mapper.notify_cpu_cycle(self.cycles + self.access_cycle as u64);
+                mapper.cpu_write(addr, data);
+                data
+            }
         }
     }
 }
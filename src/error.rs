@@ -0,0 +1,71 @@
+use std::fmt;
+use std::io;
+
+/// エミュレータの公開 API が返すエラー
+#[derive(Debug)]
+pub enum NesError {
+    /// 先頭 4 byte が "NES" + 0x1A でない
+    BadMagic,
+    /// ヘッダが示すサイズよりファイルが短い
+    Truncated {
+        /// どこを読んでいる途中か (header, trainer, PRG ROM, CHR ROM)
+        section: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    /// 未対応のマッパー番号
+    UnsupportedMapper(u16),
+    /// PRG ROM が 0 byte などヘッダの値がおかしい
+    InvalidHeader(&'static str),
+    /// バッテリーバックアップされた PRG RAM がないのにセーブデータを読み込もうとした
+    NoBatteryRam,
+    /// セーブデータの大きさが PRG RAM と違う
+    SaveSizeMismatch { expected: usize, actual: usize },
+    /// CHR ROM のカートリッジに CHR RAM の中身を読み込もうとした
+    NoChrRam,
+    Io(io::Error),
+}
+
+impl fmt::Display for NesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NesError::BadMagic => write!(f, "not nes file"),
+            NesError::Truncated {
+                section,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "truncated {}: expected {} bytes, but only {} bytes left",
+                section, expected, actual
+            ),
+            NesError::UnsupportedMapper(mapper) => write!(f, "unsupported mapper: {}", mapper),
+            NesError::InvalidHeader(reason) => write!(f, "invalid header: {}", reason),
+            NesError::NoBatteryRam => write!(f, "cartridge has no battery backed RAM"),
+            NesError::SaveSizeMismatch { expected, actual } => write!(
+                f,
+                "save data size mismatch: expected {} bytes, but got {} bytes",
+                expected, actual
+            ),
+            NesError::NoChrRam => write!(f, "cartridge has no CHR RAM"),
+            NesError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for NesError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NesError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NesError {
+    fn from(e: io::Error) -> Self {
+        NesError::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, NesError>;
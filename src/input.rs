@@ -0,0 +1,231 @@
+mod arkanoid;
+mod four_player;
+mod joypad;
+mod keyboard;
+mod power_pad;
+mod zapper;
+
+pub use arkanoid::{Arkanoid, ArkanoidState};
+pub use four_player::{FamicomFourPlayer, FourScore};
+pub use joypad::{ButtonState, Joypad};
+pub use keyboard::{Keyboard, KeyboardState, KEYBOARD_ROWS};
+pub use power_pad::{PowerPad, PowerPadState};
+pub use zapper::{Zapper, ZapperState};
+
+use std::any::Any;
+
+/// コントローラポートや拡張端子につなぐ機器
+/// ホスト側の状態は機器ごとの型 (ButtonState, ZapperState など) で渡す
+pub trait InputDevice {
+    /// $4016 への書き込み
+    /// bit 0 (OUT0) がストローブで, 両方のポートと拡張端子に同じ値が届く
+    fn write(&mut self, data: u8);
+
+    /// port: 0 ($4016) / 1 ($4017) の読み込み
+    /// ポートの機器は D0-D4, 拡張端子の機器は D1-D4 だけがつながっていて,
+    /// 上位 3bit は CpuBus が open bus で埋める
+    fn read(&mut self, port: usize) -> u8;
+
+    /// ホスト側から機器ごとの状態を渡すときに具体的な型に戻す
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// 4 人で遊ぶためのアダプタ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FourPlayerAdapter {
+    /// NES Four Score, 両方のポートにささる
+    FourScore,
+    /// ファミコンの拡張端子につなぐもの, 1P, 2P は本体のコントローラのまま
+    Famicom,
+}
+
+/// 本体の 2 つのコントローラポートと (ファミコンの) 拡張端子
+/// 電源投入時は両方に標準コントローラがささっていて, 拡張端子には何もない
+/// Zapper などはホスト側から差し替える
+pub struct ControllerPorts {
+    devices: [Box<dyn InputDevice>; 2],
+    expansion: Option<Box<dyn InputDevice>>,
+}
+
+impl ControllerPorts {
+    pub fn new() -> Self {
+        ControllerPorts {
+            devices: [Box::new(Joypad::new()), Box::new(Joypad::new())],
+            expansion: None,
+        }
+    }
+
+    /// port: 0 (1P, $4016) か 1 (2P, $4017), それ以外は無視する
+    pub fn connect(&mut self, port: usize, device: Box<dyn InputDevice>) {
+        if let Some(slot) = self.devices.get_mut(port) {
+            *slot = device;
+        }
+    }
+
+    pub fn connect_expansion(&mut self, device: Option<Box<dyn InputDevice>>) {
+        self.expansion = device;
+    }
+
+    /// 4 人用のアダプタにつなぎかえる
+    /// 前につながっていた機器は外れる
+    pub fn connect_four_player(&mut self, adapter: FourPlayerAdapter) {
+        match adapter {
+            FourPlayerAdapter::FourScore => {
+                self.devices = [Box::new(FourScore::new(0)), Box::new(FourScore::new(1))];
+                self.expansion = None;
+            }
+            FourPlayerAdapter::Famicom => {
+                self.devices = [Box::new(Joypad::new()), Box::new(Joypad::new())];
+                self.expansion = Some(Box::new(FamicomFourPlayer::new()));
+            }
+        }
+    }
+
+    /// port につながっている機器が T ならそれを返す
+    pub fn device_mut<T: 'static>(&mut self, port: usize) -> Option<&mut T> {
+        self.devices
+            .get_mut(port)
+            .and_then(|device| device.as_any_mut().downcast_mut::<T>())
+    }
+
+    /// 拡張端子につながっている機器が T ならそれを返す
+    pub fn expansion_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.expansion
+            .as_mut()
+            .and_then(|device| device.as_any_mut().downcast_mut::<T>())
+    }
+
+    /// player: 0-3 (1P-4P)
+    /// 3P, 4P は 4 人用のアダプタがつながっているときだけ
+    /// そのプレイヤーのコントローラがなければ何もしない
+    pub fn set_buttons(&mut self, player: usize, buttons: ButtonState) {
+        if player >= 4 {
+            return;
+        }
+        let port = player % 2;
+        if player < 2 {
+            if let Some(joypad) = self.device_mut::<Joypad>(port) {
+                joypad.set_buttons(buttons);
+                return;
+            }
+        }
+        if let Some(four_score) = self.device_mut::<FourScore>(port) {
+            four_score.set_buttons(player / 2, buttons);
+            return;
+        }
+        if player >= 2 {
+            if let Some(adapter) = self.expansion_mut::<FamicomFourPlayer>() {
+                adapter.set_buttons(port, buttons);
+            }
+        }
+    }
+
+    /// $4016 の書き込み
+    pub fn write(&mut self, data: u8) {
+        for device in self.devices.iter_mut().chain(self.expansion.iter_mut()) {
+            device.write(data);
+        }
+    }
+
+    /// $4016 / $4017 の読み込み, D0-D4 以外は 0
+    pub fn read(&mut self, port: usize) -> u8 {
+        let expansion = match self.expansion.as_mut() {
+            Some(device) => device.read(port) & 0x1e,
+            None => 0,
+        };
+        (self.devices[port].read(port) & 0x1f) | expansion
+    }
+}
+
+impl Default for ControllerPorts {
+    fn default() -> Self {
+        ControllerPorts::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ストローブしてから n 回読む
+    fn read_bits(ports: &mut ControllerPorts, port: usize, n: usize) -> Vec<u8> {
+        ports.write(1);
+        ports.write(0);
+        (0..n).map(|_| ports.read(port)).collect()
+    }
+
+    /// bit 目を 8 回分ずつまとめる
+    fn bytes(bits: &[u8], bit: u8) -> Vec<u8> {
+        bits.chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |byte, (i, b)| byte | ((b >> bit) & 0x01) << i)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn four_score() {
+        let mut ports = ControllerPorts::new();
+        ports.connect_four_player(FourPlayerAdapter::FourScore);
+        for player in 0..4 {
+            ports.set_buttons(player, ButtonState::from_bits(0x11 << player));
+        }
+        // 1P, 3P, 識別子 $10 (の後は 1)
+        let bits = read_bits(&mut ports, 0, 32);
+        assert_eq!(bytes(&bits, 0), vec![0x11, 0x44, 0x10, 0xff]);
+        // 2P, 4P, 識別子 $20
+        let bits = read_bits(&mut ports, 1, 24);
+        assert_eq!(bytes(&bits, 0), vec![0x22, 0x88, 0x20]);
+    }
+
+    #[test]
+    fn famicom_four_player() {
+        let mut ports = ControllerPorts::new();
+        ports.connect_four_player(FourPlayerAdapter::Famicom);
+        for player in 0..4 {
+            ports.set_buttons(player, ButtonState::from_bits(0x11 << player));
+        }
+        // D0 が 1P, D1 が 3P
+        let bits = read_bits(&mut ports, 0, 8);
+        assert_eq!(bytes(&bits, 0), vec![0x11]);
+        assert_eq!(bytes(&bits, 1), vec![0x44]);
+        let bits = read_bits(&mut ports, 1, 8);
+        assert_eq!(bytes(&bits, 0), vec![0x22]);
+        assert_eq!(bytes(&bits, 1), vec![0x88]);
+
+        // アダプタがなければ 3P, 4P は無視される
+        let mut ports = ControllerPorts::new();
+        ports.set_buttons(2, ButtonState::from_bits(0xff));
+        assert_eq!(read_bits(&mut ports, 0, 8), vec![0; 8]);
+    }
+
+    #[test]
+    fn connect_out_of_range() {
+        let mut ports = ControllerPorts::new();
+        ports.connect(2, Box::new(PowerPad::new()));
+        assert!(ports.device_mut::<Joypad>(0).is_some());
+        assert!(ports.device_mut::<Joypad>(1).is_some());
+        assert!(ports.device_mut::<PowerPad>(2).is_none());
+    }
+
+    #[test]
+    fn set_buttons_out_of_range() {
+        // 5P 以降はどのアダプタでも無視される
+        let mut ports = ControllerPorts::new();
+        ports.connect_four_player(FourPlayerAdapter::FourScore);
+        ports.set_buttons(4, ButtonState::from_bits(0xff));
+        ports.set_buttons(5, ButtonState::from_bits(0xff));
+        assert_eq!(bytes(&read_bits(&mut ports, 0, 24), 0), vec![0x00, 0x00, 0x10]);
+        assert_eq!(bytes(&read_bits(&mut ports, 1, 24), 0), vec![0x00, 0x00, 0x20]);
+
+        let mut ports = ControllerPorts::new();
+        ports.connect_four_player(FourPlayerAdapter::Famicom);
+        ports.set_buttons(4, ButtonState::from_bits(0xff));
+        ports.set_buttons(5, ButtonState::from_bits(0xff));
+        assert_eq!(read_bits(&mut ports, 0, 8), vec![0; 8]);
+        assert_eq!(read_bits(&mut ports, 1, 8), vec![0; 8]);
+    }
+}
@@ -0,0 +1,102 @@
+use super::InputDevice;
+use std::any::Any;
+
+/// ホスト側のつまみとボタンの状態
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ArkanoidState {
+    /// つまみの位置, 実機ではおよそ 98 (左端) から 242 (右端)
+    pub position: u8,
+    pub fire: bool,
+}
+
+/// アルカノイドのコントローラ (Vaus)
+/// ストローブでつまみの位置をラッチし, 読むたびに MSB から 1 bit ずつ反転して出てくる
+/// - NES 版 (2P 側のポート): $4017 の D3 がボタン, D4 が位置
+/// - ファミコン版 (拡張端子): $4016 の D1 がボタン, $4017 の D1 が位置
+#[derive(Default)]
+pub struct Arkanoid {
+    famicom: bool,
+    state: ArkanoidState,
+    strobe: bool,
+    shift: u8,
+}
+
+impl Arkanoid {
+    /// NES 版, 2P 側のポートにつなぐ
+    pub fn new() -> Self {
+        Arkanoid::default()
+    }
+
+    /// ファミコン版, 拡張端子につなぐ
+    pub fn famicom() -> Self {
+        Arkanoid {
+            famicom: true,
+            ..Arkanoid::new()
+        }
+    }
+
+    pub fn set_state(&mut self, state: ArkanoidState) {
+        self.state = state;
+    }
+
+    /// 位置の次の 1 bit (反転済み)
+    fn next_bit(&mut self) -> u8 {
+        if self.strobe {
+            return !self.state.position >> 7;
+        }
+        let bit = !self.shift >> 7;
+        self.shift <<= 1;
+        bit
+    }
+}
+
+impl InputDevice for Arkanoid {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 0x01 != 0;
+        if self.strobe {
+            self.shift = self.state.position;
+        }
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        let fire = self.state.fire as u8;
+        match (self.famicom, port) {
+            (false, _) => fire << 3 | self.next_bit() << 4,
+            (true, 0) => fire << 1,
+            (true, _) => self.next_bit() << 1,
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serial_position() {
+        let mut vaus = Arkanoid::new();
+        vaus.set_state(ArkanoidState {
+            position: 0b1010_0011,
+            fire: true,
+        });
+        vaus.write(1);
+        vaus.write(0);
+        let bits: Vec<u8> = (0..8).map(|_| vaus.read(1)).collect();
+        assert_eq!(bits, vec![0x08, 0x18, 0x08, 0x18, 0x18, 0x18, 0x08, 0x08]);
+
+        let mut vaus = Arkanoid::famicom();
+        vaus.set_state(ArkanoidState {
+            position: 0x80,
+            fire: true,
+        });
+        vaus.write(1);
+        vaus.write(0);
+        assert_eq!(vaus.read(0), 0x02);
+        assert_eq!(vaus.read(1), 0x00);
+        assert_eq!(vaus.read(1), 0x02);
+    }
+}
@@ -0,0 +1,95 @@
+use super::{ButtonState, InputDevice, Joypad};
+use std::any::Any;
+
+/// NES Four Score の片側
+/// 1 つのポートに 2 人分つながり, ストローブ後に 8 bit ずつ 2 人分, 続けて 8 bit の識別子が出てくる
+/// $4016 側は 1P, 3P, 識別子 $10, $4017 側は 2P, 4P, 識別子 $20
+/// 24 回読んだ後は 1 が返る
+pub struct FourScore {
+    buttons: [ButtonState; 2],
+    signature: u8,
+    strobe: bool,
+    shift: u32,
+}
+
+impl FourScore {
+    /// port: 0 ($4016) か 1 ($4017)
+    pub fn new(port: usize) -> Self {
+        FourScore {
+            buttons: [ButtonState::default(); 2],
+            signature: if port == 0 { 0x10 } else { 0x20 },
+            strobe: false,
+            shift: 0,
+        }
+    }
+
+    /// index: 0 なら 1P / 2P, 1 なら 3P / 4P
+    pub fn set_buttons(&mut self, index: usize, buttons: ButtonState) {
+        self.buttons[index] = buttons;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    fn reload(&mut self) {
+        self.shift = self.buttons[0].bits() as u32
+            | (self.buttons[1].bits() as u32) << 8
+            | (self.signature as u32) << 16;
+    }
+}
+
+impl InputDevice for FourScore {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 0x01 != 0;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    fn read(&mut self, _port: usize) -> u8 {
+        if self.strobe {
+            return self.buttons[0].a as u8;
+        }
+        let bit = (self.shift & 0x01) as u8;
+        self.shift = (self.shift >> 1) | 0x0080_0000;
+        bit
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// ファミコンの拡張端子につなぐ 4 人用アダプタ
+/// 3P は $4016 の D1, 4P は $4017 の D1 に出てくる (識別子はない)
+#[derive(Default)]
+pub struct FamicomFourPlayer {
+    pads: [Joypad; 2],
+}
+
+impl FamicomFourPlayer {
+    pub fn new() -> Self {
+        FamicomFourPlayer::default()
+    }
+
+    /// index: 0 なら 3P, 1 なら 4P
+    pub fn set_buttons(&mut self, index: usize, buttons: ButtonState) {
+        self.pads[index].set_buttons(buttons);
+    }
+}
+
+impl InputDevice for FamicomFourPlayer {
+    fn write(&mut self, data: u8) {
+        for pad in self.pads.iter_mut() {
+            pad.write(data);
+        }
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        self.pads[port].read(port) << 1
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,121 @@
+use super::InputDevice;
+use std::any::Any;
+
+/// 標準コントローラのボタンの状態
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ButtonState {
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl ButtonState {
+    /// 読み出される順に bit 0 から並べる
+    /// [RLDU TSBA]: u8
+    pub fn bits(&self) -> u8 {
+        (self.a as u8)
+            | (self.b as u8) << 1
+            | (self.select as u8) << 2
+            | (self.start as u8) << 3
+            | (self.up as u8) << 4
+            | (self.down as u8) << 5
+            | (self.left as u8) << 6
+            | (self.right as u8) << 7
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        ButtonState {
+            a: bits & 0x01 != 0,
+            b: bits & 0x02 != 0,
+            select: bits & 0x04 != 0,
+            start: bits & 0x08 != 0,
+            up: bits & 0x10 != 0,
+            down: bits & 0x20 != 0,
+            left: bits & 0x40 != 0,
+            right: bits & 0x80 != 0,
+        }
+    }
+}
+
+/// 標準コントローラ
+/// ストローブが 1 の間はボタンの状態をシフトレジスタに読み込み続け,
+/// 0 にすると読むたびに A, B, Select, Start, 上, 下, 左, 右 の順に D0 に出てくる
+/// 8 回読んだ後は 1 が返る
+#[derive(Default)]
+pub struct Joypad {
+    buttons: ButtonState,
+    strobe: bool,
+    shift: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad::default()
+    }
+
+    pub fn set_buttons(&mut self, buttons: ButtonState) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.shift = buttons.bits();
+        }
+    }
+}
+
+impl InputDevice for Joypad {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 0x01 != 0;
+        if self.strobe {
+            self.shift = self.buttons.bits();
+        }
+    }
+
+    fn read(&mut self, _port: usize) -> u8 {
+        if self.strobe {
+            return self.buttons.a as u8;
+        }
+        let bit = self.shift & 0x01;
+        self.shift = (self.shift >> 1) | 0x80;
+        bit
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shift_register() {
+        let mut joypad = Joypad::new();
+        joypad.set_buttons(ButtonState {
+            a: true,
+            start: true,
+            left: true,
+            ..ButtonState::default()
+        });
+        // ストローブ中は A だけが見える
+        joypad.write(1);
+        assert_eq!(joypad.read(0), 1);
+        assert_eq!(joypad.read(0), 1);
+
+        joypad.write(0);
+        let bits: Vec<u8> = (0..10).map(|_| joypad.read(0)).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 1, 0, 1, 1]);
+
+        // 次のストローブまでは押されても変わらない
+        joypad.set_buttons(ButtonState::from_bits(0xff));
+        assert_eq!(joypad.read(0), 1);
+        joypad.write(1);
+        joypad.write(0);
+        assert_eq!(joypad.read(0), 1);
+        assert_eq!(ButtonState::from_bits(0x5a).bits(), 0x5a);
+    }
+}
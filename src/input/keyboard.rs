@@ -0,0 +1,116 @@
+use super::InputDevice;
+use std::any::Any;
+
+/// キーマトリクスの行数
+pub const KEYBOARD_ROWS: usize = 9;
+
+/// ホスト側のキーの状態
+/// keys[行][キー], 押されていたら true
+/// 各行のキー 0-3 が列 0, キー 4-7 が列 1 で, D1 から順に出てくる
+///
+/// | 行 | 0     | 1     | 2      | 3        | 4    | 5   | 6      | 7      |
+/// |----|-------|-------|--------|----------|------|-----|--------|--------|
+/// | 0  | ]     | [     | RETURN | F8       | STOP | ¥   | RSHIFT | カナ   |
+/// | 1  | ;     | :     | @      | F7       | ^    | -   | /      | _      |
+/// | 2  | K     | L     | O      | F6       | 0    | P   | ,      | .      |
+/// | 3  | J     | U     | I      | F5       | 8    | 9   | N      | M      |
+/// | 4  | H     | G     | Y      | F4       | 6    | 7   | V      | B      |
+/// | 5  | D     | R     | T      | F3       | 4    | 5   | C      | F      |
+/// | 6  | A     | S     | W      | F2       | 3    | E   | Z      | X      |
+/// | 7  | CTR   | Q     | ESC    | F1       | 2    | 1   | GRPH   | LSHIFT |
+/// | 8  | ←     | →     | ↑      | CLR HOME | INS  | DEL | SPACE  | ↓      |
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct KeyboardState {
+    pub keys: [[bool; 8]; KEYBOARD_ROWS],
+}
+
+/// ファミリーベーシックのキーボード, 拡張端子につなぐ
+/// $4016 への書き込み [.... .KCR]: K: キーボード有効, C: 列, R: 行を 0 に戻す
+/// C を 1 から 0 にすると次の行に進む
+/// $4017 の D1-D4 に今の行と列の 4 キーが反転して (押されていたら 0) 出てくる
+#[derive(Default)]
+pub struct Keyboard {
+    state: KeyboardState,
+    enabled: bool,
+    column: usize,
+    row: usize,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Keyboard::default()
+    }
+
+    pub fn set_state(&mut self, state: KeyboardState) {
+        self.state = state;
+    }
+}
+
+impl InputDevice for Keyboard {
+    fn write(&mut self, data: u8) {
+        let column = ((data >> 1) & 0x01) as usize;
+        if data & 0x01 != 0 {
+            self.row = 0;
+        } else if self.column == 1 && column == 0 {
+            self.row += 1;
+        }
+        self.column = column;
+        self.enabled = data & 0x04 != 0;
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        if port == 0 || !self.enabled {
+            return 0;
+        }
+        let keys = match self.state.keys.get(self.row) {
+            Some(keys) => &keys[self.column * 4..self.column * 4 + 4],
+            // 最後の行より後ろは何も押されていない
+            None => return 0x1e,
+        };
+        keys.iter()
+            .enumerate()
+            .fold(0, |bits, (i, pressed)| bits | ((!pressed) as u8) << (i + 1))
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_matrix() {
+        let mut keyboard = Keyboard::new();
+        let mut state = KeyboardState::default();
+        // RETURN と SPACE
+        state.keys[0][2] = true;
+        state.keys[8][6] = true;
+        keyboard.set_state(state);
+
+        // 無効なときは何も出ない
+        assert_eq!(keyboard.read(1), 0x00);
+
+        // 行 0 から列 0, 列 1 の順に読む
+        keyboard.write(0x05);
+        let mut scanned = vec![];
+        for _ in 0..KEYBOARD_ROWS + 1 {
+            keyboard.write(0x04);
+            scanned.push(keyboard.read(1));
+            keyboard.write(0x06);
+            scanned.push(keyboard.read(1));
+        }
+        assert_eq!(scanned[0], 0x1e & !0x08);
+        assert_eq!(scanned[1], 0x1e);
+        assert_eq!(scanned[17], 0x1e & !0x08);
+        assert_eq!(scanned[18], 0x1e);
+        assert!(scanned
+            .iter()
+            .enumerate()
+            .all(|(i, bits)| i == 0 || i == 17 || *bits == 0x1e));
+        // $4016 には何も出ない
+        assert_eq!(keyboard.read(0), 0x00);
+    }
+}
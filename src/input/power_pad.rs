@@ -0,0 +1,88 @@
+use super::InputDevice;
+use std::any::Any;
+
+/// D3 から出てくるボタンの順 (1-12 の番号)
+const D3_ORDER: [usize; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+/// D4 から出てくるボタンの順, 残りの 4 bit は 1
+const D4_ORDER: [usize; 4] = [4, 3, 12, 8];
+
+/// ホスト側のマットの状態
+/// buttons[0] がボタン 1 (B 面の番号), 押されていたら true
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PowerPadState {
+    pub buttons: [bool; 12],
+}
+
+/// パワーパッド (ファミリートレーナー), 2P 側のポートにつなぐ
+/// ストローブで 12 個のボタンを 2 本のシフトレジスタにラッチし, D3 と D4 から 1 bit ずつ出す
+#[derive(Default)]
+pub struct PowerPad {
+    state: PowerPadState,
+    strobe: bool,
+    d3: u8,
+    d4: u8,
+}
+
+impl PowerPad {
+    pub fn new() -> Self {
+        PowerPad::default()
+    }
+
+    pub fn set_state(&mut self, state: PowerPadState) {
+        self.state = state;
+    }
+
+    fn latch(&mut self) {
+        let buttons = self.state.buttons;
+        let pressed = |n: &usize| buttons[n - 1] as u8;
+        self.d3 = D3_ORDER.iter().enumerate().fold(0, |d3, (i, n)| d3 | pressed(n) << i);
+        self.d4 = D4_ORDER.iter().enumerate().fold(0xf0, |d4, (i, n)| d4 | pressed(n) << i);
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 0x01 != 0;
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    fn read(&mut self, _port: usize) -> u8 {
+        if self.strobe {
+            self.latch();
+        }
+        let bits = (self.d3 & 0x01) << 3 | (self.d4 & 0x01) << 4;
+        if !self.strobe {
+            self.d3 = (self.d3 >> 1) | 0x80;
+            self.d4 = (self.d4 >> 1) | 0x80;
+        }
+        bits
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serial_buttons() {
+        let mut pad = PowerPad::new();
+        let mut state = PowerPadState::default();
+        // 1 と 12 を踏む
+        state.buttons[0] = true;
+        state.buttons[11] = true;
+        pad.set_state(state);
+        pad.write(1);
+        pad.write(0);
+        let bits: Vec<u8> = (0..10).map(|_| pad.read(1)).collect();
+        assert_eq!(
+            bits,
+            vec![0x00, 0x08, 0x10, 0x00, 0x10, 0x10, 0x10, 0x10, 0x18, 0x18]
+        );
+    }
+}
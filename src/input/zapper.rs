@@ -0,0 +1,85 @@
+use super::InputDevice;
+use crate::ppu::Ppu;
+use crate::screen::{self, SCREEN_SIZE};
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// 照準の周り何 px の光を拾うか
+const SENSE_RADIUS: usize = 2;
+/// ビームが通ってから何ライン分明るく見えるか (ブラウン管の残光)
+const DECAY_LINES: usize = 24;
+/// 光っているとみなす輝度 (0-255)
+const LIGHT_THRESHOLD: u32 = 0x80;
+
+/// ホスト側のポインタの状態
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ZapperState {
+    /// 画面上の照準 (x: 0-255, y: 0-239), 画面の外を向いていたら None
+    pub aim: Option<(usize, usize)>,
+    pub trigger: bool,
+}
+
+/// 光線銃, 2P 側のポートにつなぐ
+/// 照準の周りのピクセルをビームが描いてすぐなら光を検出する
+pub struct Zapper {
+    ppu: Rc<RefCell<Ppu>>,
+    state: ZapperState,
+}
+
+impl Zapper {
+    pub fn new(ppu: Rc<RefCell<Ppu>>) -> Self {
+        Zapper {
+            ppu,
+            state: ZapperState::default(),
+        }
+    }
+
+    pub fn set_state(&mut self, state: ZapperState) {
+        self.state = state;
+    }
+
+    /// 照準の周りに, 今のフレームで描かれてから DECAY_LINES 以内の明るいピクセルがあるか
+    fn detect_light(&self) -> bool {
+        let (x, y) = match self.state.aim {
+            Some(aim) => aim,
+            None => return false,
+        };
+        let ppu = self.ppu.borrow();
+        let (line, dot) = ppu.beam();
+        let bottom = (y + SENSE_RADIUS).min(SCREEN_SIZE.1 - 1);
+        let right = (x + SENSE_RADIUS).min(SCREEN_SIZE.0 - 1);
+        for py in y.saturating_sub(SENSE_RADIUS)..=bottom {
+            // ビームがまだ来ていないか, 通ってから暗くなった
+            if py > line || line - py > DECAY_LINES {
+                continue;
+            }
+            for px in x.saturating_sub(SENSE_RADIUS)..=right {
+                // x のピクセルはドット x + 1 で出力される
+                if py == line && px + 1 >= dot {
+                    continue;
+                }
+                let (r, g, b) = screen::pixel_to_rgb(ppu.pixel(px, py));
+                let luminance = (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+                if luminance >= LIGHT_THRESHOLD {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl InputDevice for Zapper {
+    fn write(&mut self, _data: u8) {}
+
+    /// [...TL ...]: u8
+    /// T: トリガーを引いている, L: 光を検出して *いない*
+    fn read(&mut self, _port: usize) -> u8 {
+        (!self.detect_light() as u8) << 3 | (self.state.trigger as u8) << 4
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
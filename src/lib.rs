@@ -1,10 +1,17 @@
+mod apu;
+pub mod cartridge;
 mod cpu;
 mod cpu_bus;
+pub mod error;
+pub mod input;
+mod mapper;
 pub mod nes;
 mod ppu;
 mod screen;
 mod wram;
 
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::f64;
 use std::panic;
 use wasm_bindgen::prelude::*;
@@ -20,6 +27,26 @@ use screen::{SCREEN_SIZE, DEBUG_SCREEN_SIZE};
 
 static program: &'static [u8] = include_bytes!("../sample1/sample1.nes");
 
+/// JS 側に渡すまでためておく音声の上限 (1 秒分くらい)
+const AUDIO_CAPACITY: usize = 48000;
+
+thread_local! {
+    /// 最新のセーブデータ (.sav), フレームごとに更新する
+    static SAVE_DATA: RefCell<Option<Vec<u8>>> = RefCell::new(None);
+    /// JS 側から渡されたまだ反映していないセーブデータ
+    static PENDING_SAVE_DATA: RefCell<Option<Vec<u8>>> = RefCell::new(None);
+    /// NES から取り出してまだ JS 側に渡していない音声
+    static AUDIO: RefCell<VecDeque<f32>> = RefCell::new(VecDeque::new());
+    /// JS 側から渡されたまだ反映していないサンプリング周波数
+    static PENDING_SAMPLE_RATE: RefCell<Option<f64>> = RefCell::new(None);
+    /// JS 側から渡された 1P-4P のボタン
+    static BUTTONS: RefCell<[u8; 4]> = RefCell::new([0; 4]);
+    /// JS 側から渡されたまだ反映していない 4 人用のアダプタ
+    static PENDING_FOUR_PLAYER: RefCell<Option<input::FourPlayerAdapter>> = RefCell::new(None);
+    /// JS 側から渡されたまだ反映していない Zapper の状態
+    static PENDING_ZAPPER: RefCell<Option<input::ZapperState>> = RefCell::new(None);
+}
+
 async fn execute() -> Result<(), JsValue> {
     let window = web_sys::window().unwrap();
     let document = window.document().unwrap();
@@ -36,7 +63,7 @@ async fn execute() -> Result<(), JsValue> {
         .dyn_into::<web_sys::CanvasRenderingContext2d>()
         .unwrap();
 
-    let mut nes = nes::NES::load(program.to_vec());
+    let mut nes = nes::NES::load(program.to_vec()).map_err(|e| JsValue::from_str(&e.to_string()))?;
     let p = program
         .to_vec()
         .iter()
@@ -45,6 +72,8 @@ async fn execute() -> Result<(), JsValue> {
     console::log_1(&p.into());
 
     let mut cycles = 0;
+    let mut audio = vec![0.0; AUDIO_CAPACITY];
+    let mut zapper_connected = false;
     // let hz = 179_0000i32;
     let fps = 1;
     loop {
@@ -54,6 +83,43 @@ async fn execute() -> Result<(), JsValue> {
         // 1 frame
         if cycles % 1000 == 0 {
             // 60 fps
+            // セーブデータの受け渡し
+            if let Some(data) = PENDING_SAVE_DATA.with(|d| d.borrow_mut().take()) {
+                if let Err(e) = nes.load_save_data(&data) {
+                    console::log_1(&e.to_string().into());
+                }
+            }
+            SAVE_DATA.with(|d| *d.borrow_mut() = nes.save_data());
+
+            // コントローラ
+            if let Some(adapter) = PENDING_FOUR_PLAYER.with(|a| a.borrow_mut().take()) {
+                nes.connect_four_player(adapter);
+            }
+            let buttons = BUTTONS.with(|b| *b.borrow());
+            for (port, bits) in buttons.iter().enumerate() {
+                nes.set_buttons(port, input::ButtonState::from_bits(*bits));
+            }
+            // 一度でも Zapper の状態が渡されたら 2P 側を Zapper にする
+            if let Some(state) = PENDING_ZAPPER.with(|z| z.borrow_mut().take()) {
+                if !zapper_connected {
+                    nes.connect_zapper();
+                    zapper_connected = true;
+                }
+                nes.set_zapper(state);
+            }
+
+            // 音声の受け渡し
+            if let Some(rate) = PENDING_SAMPLE_RATE.with(|r| r.borrow_mut().take()) {
+                nes.set_sample_rate(rate);
+            }
+            let len = nes.drain_audio(&mut audio);
+            AUDIO.with(|a| {
+                let mut a = a.borrow_mut();
+                a.extend(&audio[..len]);
+                let overflow = a.len().saturating_sub(AUDIO_CAPACITY);
+                a.drain(..overflow);
+            });
+
             let mut screen = nes.ppu.borrow().ppu_bus.screen.screen.clone();
             let mut debug_screen = nes.ppu.borrow().ppu_bus.screen.debug_screen.clone();
             let ppu = nes.ppu.borrow();
@@ -85,6 +151,7 @@ async fn execute() -> Result<(), JsValue> {
 
 
             console::log_1(&"complete rendering".into());
+
             cycles = 0;
 
             // sleep
@@ -99,6 +166,70 @@ async fn execute() -> Result<(), JsValue> {
     }
 }
 
+/// バッテリーバックアップされた PRG RAM の中身 (.sav) を取り出す
+#[wasm_bindgen]
+pub fn export_save_data() -> Option<Vec<u8>> {
+    SAVE_DATA.with(|d| d.borrow().clone())
+}
+
+/// .sav の中身を渡す, 次のフレームで PRG RAM に反映される
+#[wasm_bindgen]
+pub fn import_save_data(data: Vec<u8>) {
+    PENDING_SAVE_DATA.with(|d| *d.borrow_mut() = Some(data));
+}
+
+/// 1P (port 0), 2P (port 1) のボタンを渡す
+/// 4 人用のアダプタをつないでいれば 3P (port 2), 4P (port 3) も
+/// bits: [右 左 下 上 Start Select B A] の順に bit 7 から
+#[wasm_bindgen]
+pub fn set_buttons(port: usize, bits: u8) {
+    BUTTONS.with(|b| {
+        if let Some(state) = b.borrow_mut().get_mut(port) {
+            *state = bits;
+        }
+    });
+}
+
+/// 4 人用のアダプタをつなぐ, famicom が false なら Four Score
+#[wasm_bindgen]
+pub fn connect_four_player(famicom: bool) {
+    let adapter = if famicom {
+        input::FourPlayerAdapter::Famicom
+    } else {
+        input::FourPlayerAdapter::FourScore
+    };
+    PENDING_FOUR_PLAYER.with(|a| *a.borrow_mut() = Some(adapter));
+}
+
+/// canvas 上のポインタの位置 (NES の画面の座標) と Zapper のトリガーを渡す
+/// 画面の外なら x, y に負の値を渡す
+#[wasm_bindgen]
+pub fn set_zapper(x: i32, y: i32, trigger: bool) {
+    let on_screen = (0..SCREEN_SIZE.0 as i32).contains(&x) && (0..SCREEN_SIZE.1 as i32).contains(&y);
+    let aim = if on_screen { Some((x as usize, y as usize)) } else { None };
+    PENDING_ZAPPER.with(|z| *z.borrow_mut() = Some(input::ZapperState { aim, trigger }));
+}
+
+/// たまっている音声 (モノラル, f32) を out の先頭から詰めて, 書き込んだサンプル数を返す
+/// AudioWorklet などから定期的に呼ぶ
+#[wasm_bindgen]
+pub fn drain_audio(out: &mut [f32]) -> usize {
+    AUDIO.with(|a| {
+        let mut a = a.borrow_mut();
+        let len = out.len().min(a.len());
+        for (o, s) in out.iter_mut().zip(a.drain(..len)) {
+            *o = s;
+        }
+        len
+    })
+}
+
+/// AudioContext.sampleRate を渡す, 次のフレームから反映される
+#[wasm_bindgen]
+pub fn set_sample_rate(sample_rate: f64) {
+    PENDING_SAMPLE_RATE.with(|r| *r.borrow_mut() = Some(sample_rate));
+}
+
 #[wasm_bindgen]
 pub async fn start() {
     panic::set_hook(Box::new(console_error_panic_hook::hook));
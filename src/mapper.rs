@@ -0,0 +1,82 @@
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::error::{NesError, Result};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod axrom;
+mod chr;
+mod cnrom;
+mod mmc1;
+mod mmc3;
+mod nrom;
+mod prg_ram;
+mod uxrom;
+
+pub use chr::Chr;
+pub use prg_ram::PrgRam;
+
+/// カートリッジ上の基板
+/// CPU から見た $4020-$FFFF と PPU から見た $0000-$1FFF (パターンテーブル) を担当する
+pub trait Mapper {
+    /// CPU $4020-$FFFF の読み込み
+    /// 何もつながっていなければ None (open bus)
+    fn cpu_read(&mut self, addr: u16) -> Option<u8>;
+
+    /// CPU $4020-$FFFF の書き込み
+    /// ROM への書き込みはバンク切り替えなどのレジスタとして解釈される
+    fn cpu_write(&mut self, addr: u16, data: u8);
+
+    /// PPU $0000-$1FFF の読み込み
+    fn ppu_read(&mut self, addr: u16) -> u8;
+
+    /// PPU $0000-$1FFF の書き込み
+    fn ppu_write(&mut self, addr: u16, data: u8);
+
+    /// パターンテーブルのメモリ (CHR ROM / CHR RAM)
+    /// デバッガの表示やステートの保存で使う
+    fn chr(&self) -> &Chr;
+
+    fn chr_mut(&mut self) -> &mut Chr;
+
+    /// 現在のネームテーブルのミラーリング
+    fn mirroring(&self) -> Mirroring;
+
+    /// PPU のアドレスバスに addr が乗ったことを知らせる
+    /// ppu_cycle は電源投入からの PPU のサイクル数
+    /// MMC3 はこれで A12 の立ち上がりを見てスキャンラインを数える
+    fn notify_ppu_address(&mut self, _addr: u16, _ppu_cycle: u64) {}
+
+    /// CPU が書き込む直前に, 電源投入からの CPU のサイクル数を知らせる
+    /// MMC1 はこれで連続したサイクルの書き込みを見分ける
+    fn notify_cpu_cycle(&mut self, _cpu_cycle: u64) {}
+
+    /// カートリッジの IRQ 出力 (レベルトリガ)
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// $6000-$7FFF の PRG RAM
+    fn prg_ram(&self) -> Option<&PrgRam> {
+        None
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut PrgRam> {
+        None
+    }
+}
+
+/// CpuBus と PpuBus の両方からつながる
+pub type SharedMapper = Rc<RefCell<Box<dyn Mapper>>>;
+
+/// ヘッダのマッパー番号から基板を作る
+pub fn new(cartridge: Cartridge) -> Result<Box<dyn Mapper>> {
+    match cartridge.header.mapper {
+        0 => Ok(Box::new(nrom::Nrom::new(cartridge)?)),
+        1 => Ok(Box::new(mmc1::Mmc1::new(cartridge))),
+        2 => Ok(Box::new(uxrom::Uxrom::new(cartridge))),
+        3 => Ok(Box::new(cnrom::Cnrom::new(cartridge))),
+        4 => Ok(Box::new(mmc3::Mmc3::new(cartridge))),
+        7 => Ok(Box::new(axrom::Axrom::new(cartridge))),
+        mapper => Err(NesError::UnsupportedMapper(mapper)),
+    }
+}
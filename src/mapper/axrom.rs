@@ -0,0 +1,102 @@
+use super::{Chr, Mapper, PrgRam};
+use crate::cartridge::{Cartridge, Mirroring};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+
+/// # AxROM (mapper 7)
+/// $8000-$FFFF への書き込み [...M .PPP]
+/// M: ネームテーブル (0: $2000 の 1 画面, 1: $2400 の 1 画面)
+/// PPP: 32 KiB PRG バンク
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: PrgRam,
+    bank: u8,
+}
+
+impl Axrom {
+    pub fn new(cartridge: Cartridge) -> Self {
+        let prg_ram = PrgRam::new(&cartridge, 0);
+        let chr = Chr::new(&cartridge);
+
+        Axrom {
+            prg_ram,
+            prg_rom: cartridge.prg_rom,
+            chr,
+            bank: 0,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff => self.prg_ram.read(addr),
+            0x8000..=0xffff => {
+                let offset = (self.bank & 0x07) as usize * PRG_BANK_SIZE + (addr as usize & 0x7fff);
+                Some(self.prg_rom[offset % self.prg_rom.len()])
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff => self.prg_ram.write(addr, data),
+            0x8000..=0xffff => self.bank = data,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(addr as usize & 0x1fff)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize & 0x1fff, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.bank & 0x10 == 0 {
+            Mirroring::SingleScreenLower
+        } else {
+            Mirroring::SingleScreenUpper
+        }
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn prg_ram(&self) -> Option<&PrgRam> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{ines_header, PROGRAM_ROM_SIZE};
+
+    #[test]
+    fn prg_banking_and_mirroring() {
+        let mut binary = ines_header([8, 0, 0x70, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for bank in 0..8 {
+            binary.extend(vec![bank / 2; PROGRAM_ROM_SIZE]);
+        }
+        let mut axrom = Axrom::new(Cartridge::parse(&binary).unwrap());
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenLower);
+        axrom.cpu_write(0x8000, 0x13);
+        assert_eq!(axrom.cpu_read(0x8000), Some(3));
+        assert_eq!(axrom.cpu_read(0xffff), Some(3));
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenUpper);
+    }
+}
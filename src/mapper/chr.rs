@@ -0,0 +1,56 @@
+use crate::cartridge::{Cartridge, CHARACTER_ROM_SIZE};
+
+/// PPU $0000-$1FFF につながるパターンテーブルのメモリ
+/// CHR ROM が 0 ページのカートリッジは CHR RAM を持っていて $2007 から書き込める
+pub struct Chr {
+    memory: Vec<u8>,
+    is_ram: bool,
+}
+
+impl Chr {
+    /// CHR ROM がなければヘッダのサイズ (NES 2.0) か 8 KiB の CHR RAM を確保する
+    pub fn new(cartridge: &Cartridge) -> Self {
+        if !cartridge.has_chr_ram() {
+            return Chr {
+                memory: cartridge.chr_rom.clone(),
+                is_ram: false,
+            };
+        }
+        let header = &cartridge.header;
+        let size = header.chr_ram_size + header.chr_nvram_size;
+        Chr {
+            memory: vec![0; std::cmp::max(size, CHARACTER_ROM_SIZE)],
+            is_ram: true,
+        }
+    }
+
+    /// offset はバンクを解決した後の位置, 大きさを超えたらミラーされる
+    pub fn read(&self, offset: usize) -> u8 {
+        self.memory[offset % self.memory.len()]
+    }
+
+    /// CHR ROM への書き込みは無視される
+    pub fn write(&mut self, offset: usize, data: u8) {
+        if self.is_ram {
+            let len = self.memory.len();
+            self.memory[offset % len] = data;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn is_ram(&self) -> bool {
+        self.is_ram
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// CHR ROM でも書き換えられるので注意 (ステートの復元用)
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+}
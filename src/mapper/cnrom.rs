@@ -0,0 +1,115 @@
+use super::{Chr, Mapper, PrgRam};
+use crate::cartridge::{Cartridge, Mirroring, CHARACTER_ROM_SIZE};
+
+/// # CNROM (mapper 3)
+/// PRG ROM は NROM と同じく 16 KiB / 32 KiB 固定
+/// $8000-$FFFF への書き込みで 8 KiB の CHR ROM バンクを選ぶ
+pub struct Cnrom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: PrgRam,
+    mirroring: Mirroring,
+    chr_bank: u8,
+}
+
+impl Cnrom {
+    pub fn new(cartridge: Cartridge) -> Self {
+        let prg_ram = PrgRam::new(&cartridge, 0);
+        let chr = Chr::new(&cartridge);
+
+        Cnrom {
+            prg_ram,
+            prg_rom: cartridge.prg_rom,
+            chr,
+            mirroring: cartridge.header.mirroring,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff => self.prg_ram.read(addr),
+            0x8000..=0xffff => Some(self.prg_rom[(addr as usize - 0x8000) % self.prg_rom.len()]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff => self.prg_ram.write(addr, data),
+            // バス競合: 同じアドレスの ROM の値と AND される
+            0x8000..=0xffff => self.chr_bank = data & self.cpu_read(addr).unwrap_or(0xff),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        let offset = self.chr_bank as usize * CHARACTER_ROM_SIZE + (addr as usize & 0x1fff);
+        self.chr.read(offset)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        // CHR ROM なら無視される
+        let offset = self.chr_bank as usize * CHARACTER_ROM_SIZE + (addr as usize & 0x1fff);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn prg_ram(&self) -> Option<&PrgRam> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{ines_header, PROGRAM_ROM_SIZE};
+
+    /// PRG ROM 16 KiB, CHR ROM 32 KiB, CHR の各バンクの中身はバンク番号
+    fn cnrom(prg_rom: Vec<u8>) -> Cnrom {
+        let mut binary = ines_header([1, 4, 0x30, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        binary.extend(prg_rom);
+        for bank in 0..4 {
+            binary.extend(vec![bank; CHARACTER_ROM_SIZE]);
+        }
+        Cnrom::new(Cartridge::parse(&binary).unwrap())
+    }
+
+    #[test]
+    fn chr_banking() {
+        let mut cnrom = cnrom(vec![0xea; PROGRAM_ROM_SIZE]);
+        assert_eq!(cnrom.ppu_read(0x0000), 0);
+        cnrom.cpu_write(0x8000, 2);
+        assert_eq!(cnrom.ppu_read(0x1fff), 2);
+        assert_eq!(cnrom.cpu_read(0xfffc), Some(0xea));
+    }
+
+    #[test]
+    fn bus_conflict() {
+        let mut prg_rom = vec![0xff; PROGRAM_ROM_SIZE];
+        prg_rom[0] = 0x01;
+        let mut cnrom = cnrom(prg_rom);
+        // $8000 の ROM は $01 なので 3 & 1 = 1
+        cnrom.cpu_write(0x8000, 3);
+        assert_eq!(cnrom.ppu_read(0x0000), 1);
+        cnrom.cpu_write(0x8001, 3);
+        assert_eq!(cnrom.ppu_read(0x0000), 3);
+    }
+}
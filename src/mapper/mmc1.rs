@@ -0,0 +1,277 @@
+use super::{Chr, Mapper, PrgRam};
+use crate::cartridge::{Cartridge, Mirroring, PROGRAM_RAM_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+
+/// # MMC1 (mapper 1, SxROM)
+/// $8000-$FFFF への書き込みを 5bit のシフトレジスタに LSB から貯めて
+/// 5 回目の書き込みでアドレスの bit 13-14 が指すレジスタに転送する
+/// - $8000-$9FFF: control [CPPMM]
+/// - $A000-$BFFF: CHR bank 0
+/// - $C000-$DFFF: CHR bank 1
+/// - $E000-$FFFF: PRG bank [RPPPP]
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    /// $6000-$7FFF
+    prg_ram: PrgRam,
+
+    /// シフトレジスタ, bit 0 から 5bit 貯める
+    shift: u8,
+    /// 何回書き込んだか
+    shift_count: u8,
+    /// 今の CPU のサイクル数
+    cpu_cycle: u64,
+    /// 最後に $8000-$FFFF に書き込まれたサイクル
+    /// 次のサイクルの書き込み (INC などの RMW の 2 回目) は無視される
+    last_write_cycle: Option<u64>,
+
+    /// [CPPMM]: u8
+    /// C: CHR バンクモード (0: 8 KiB, 1: 4 KiB x 2)
+    /// PP: PRG バンクモード
+    ///   0, 1: $8000 に 32 KiB 切り替え
+    ///   2: $8000 を最初のバンクに固定, $C000 を切り替え
+    ///   3: $C000 を最後のバンクに固定, $8000 を切り替え
+    /// MM: ミラーリング (0: one-screen lower, 1: one-screen upper, 2: vertical, 3: horizontal)
+    control: u8,
+    chr_bank0: u8,
+    chr_bank1: u8,
+    /// [RPPPP]: u8
+    /// R: PRG RAM 無効 (0: 有効, 1: 無効)
+    /// PPPP: 16 KiB PRG バンク
+    prg_bank: u8,
+}
+
+impl Mmc1 {
+    pub fn new(cartridge: Cartridge) -> Self {
+        let prg_ram = PrgRam::new(&cartridge, PROGRAM_RAM_SIZE);
+        let chr = Chr::new(&cartridge);
+
+        Mmc1 {
+            prg_rom: cartridge.prg_rom,
+            chr,
+            prg_ram,
+            shift: 0,
+            shift_count: 0,
+            cpu_cycle: 0,
+            last_write_cycle: None,
+            // 電源投入時は PRG モード 3
+            control: 0x0c,
+            chr_bank0: 0,
+            chr_bank1: 0,
+            prg_bank: 0,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x8000..=0x9fff => self.control = data,
+            0xa000..=0xbfff => self.chr_bank0 = data,
+            0xc000..=0xdfff => self.chr_bank1 = data,
+            0xe000..=0xffff => self.prg_bank = data,
+            _ => unreachable!(),
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0x10 == 0
+    }
+
+    /// SUROM (512 KiB) では CHR バンクの bit 4 で PRG の 256 KiB ブロックを選ぶ
+    fn prg_outer_bank(&self) -> usize {
+        if self.prg_rom.len() > 0x40000 {
+            self.chr_bank0 as usize & 0x10
+        } else {
+            0
+        }
+    }
+
+    /// CPU アドレスから PRG ROM のオフセットを求める
+    fn prg_offset(&self, addr: u16) -> usize {
+        let outer = self.prg_outer_bank();
+        let bank = (self.prg_bank & 0x0f) as usize;
+        // outer で選ばれた 256 KiB ブロックの中の最後のバンク
+        let last = outer | (std::cmp::min(self.prg_rom.len() / PRG_BANK_SIZE, 16) - 1);
+        let bank = match ((self.control >> 2) & 0x03, addr) {
+            (0, 0x8000..=0xbfff) | (1, 0x8000..=0xbfff) => outer | (bank & 0x0e),
+            (0, _) | (1, _) => outer | (bank & 0x0e) | 1,
+            (2, 0x8000..=0xbfff) => outer,
+            (2, _) => outer | bank,
+            (_, 0x8000..=0xbfff) => outer | bank,
+            (_, _) => last,
+        };
+        (bank * PRG_BANK_SIZE + (addr as usize & 0x3fff)) % self.prg_rom.len()
+    }
+
+    /// PPU アドレスから CHR のオフセットを求める
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank = if self.control & 0x10 == 0 {
+            // 8 KiB モードでは bank0 の最下位 bit を無視する
+            (self.chr_bank0 & 0x1e) as usize | (addr as usize >> 12)
+        } else if addr < 0x1000 {
+            self.chr_bank0 as usize
+        } else {
+            self.chr_bank1 as usize
+        };
+        bank * CHR_BANK_SIZE + (addr as usize & 0x0fff)
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff if self.prg_ram_enabled() => self.prg_ram.read(addr),
+            0x8000..=0xffff => Some(self.prg_rom[self.prg_offset(addr)]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff if self.prg_ram_enabled() => self.prg_ram.write(addr, data),
+            0x8000..=0xffff => {
+                let consecutive = matches!(self.last_write_cycle, Some(last) if self.cpu_cycle <= last + 1);
+                self.last_write_cycle = Some(self.cpu_cycle);
+                if consecutive {
+                    return;
+                }
+                // bit 7 が立っていたらシフトレジスタをリセット
+                if data & 0x80 != 0 {
+                    self.shift = 0;
+                    self.shift_count = 0;
+                    self.control |= 0x0c;
+                    return;
+                }
+                self.shift |= (data & 0x01) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    let value = self.shift;
+                    self.write_register(addr, value);
+                    self.shift = 0;
+                    self.shift_count = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let offset = self.chr_offset(addr);
+        self.chr.write(offset, data);
+    }
+
+    fn notify_cpu_cycle(&mut self, cpu_cycle: u64) {
+        self.cpu_cycle = cpu_cycle;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn prg_ram(&self) -> Option<&PrgRam> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{ines_header, PROGRAM_ROM_SIZE};
+
+    fn mmc1() -> Mmc1 {
+        // PRG ROM 128 KiB, CHR ROM 16 KiB, 各バンクの中身はバンク番号
+        let mut binary = ines_header([8, 2, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for bank in 0..8 {
+            binary.extend(vec![bank; PROGRAM_ROM_SIZE]);
+        }
+        for bank in 0..4 {
+            binary.extend(vec![bank; CHR_BANK_SIZE]);
+        }
+        Mmc1::new(Cartridge::parse(&binary).unwrap())
+    }
+
+    /// STA ごとに 4 サイクル空けて書き込む
+    fn write(mmc1: &mut Mmc1, addr: u16, data: u8) {
+        let cycle = mmc1.cpu_cycle + 4;
+        mmc1.notify_cpu_cycle(cycle);
+        mmc1.cpu_write(addr, data);
+    }
+
+    fn serial_write(mmc1: &mut Mmc1, addr: u16, value: u8) {
+        for i in 0..5 {
+            write(mmc1, addr, (value >> i) & 0x01);
+        }
+    }
+
+    #[test]
+    fn prg_banking() {
+        let mut mmc1 = mmc1();
+        // 電源投入時は $C000 が最後のバンクに固定
+        assert_eq!(mmc1.cpu_read(0xc000), Some(7));
+        serial_write(&mut mmc1, 0xe000, 3);
+        assert_eq!(mmc1.cpu_read(0x8000), Some(3));
+        assert_eq!(mmc1.cpu_read(0xffff), Some(7));
+
+        // 32 KiB モード
+        serial_write(&mut mmc1, 0x8000, 0b0_00_10);
+        assert_eq!(mmc1.cpu_read(0x8000), Some(2));
+        assert_eq!(mmc1.cpu_read(0xc000), Some(3));
+        assert_eq!(mmc1.mirroring(), Mirroring::Vertical);
+
+        // リセットで PRG モード 3 に戻る
+        write(&mut mmc1, 0x8000, 0x80);
+        assert_eq!(mmc1.cpu_read(0xc000), Some(7));
+    }
+
+    #[test]
+    fn consecutive_writes() {
+        let mut mmc1 = mmc1();
+        serial_write(&mut mmc1, 0x8000, 0b0_00_10);
+        // INC $FFFF ($FF): $FF でリセットされ, 次のサイクルの $00 は無視される
+        write(&mut mmc1, 0xffff, 0xff);
+        let cycle = mmc1.cpu_cycle + 1;
+        mmc1.notify_cpu_cycle(cycle);
+        mmc1.cpu_write(0xffff, 0x00);
+        assert_eq!(mmc1.cpu_read(0xc000), Some(7));
+        // シフトレジスタは空のまま
+        serial_write(&mut mmc1, 0xe000, 3);
+        assert_eq!(mmc1.cpu_read(0x8000), Some(3));
+    }
+
+    #[test]
+    fn chr_banking_and_prg_ram() {
+        let mut mmc1 = mmc1();
+        serial_write(&mut mmc1, 0x8000, 0b1_11_11);
+        serial_write(&mut mmc1, 0xa000, 3);
+        serial_write(&mut mmc1, 0xc000, 1);
+        assert_eq!(mmc1.ppu_read(0x0000), 3);
+        assert_eq!(mmc1.ppu_read(0x1000), 1);
+
+        mmc1.cpu_write(0x6000, 0x55);
+        assert_eq!(mmc1.cpu_read(0x6000), Some(0x55));
+        serial_write(&mut mmc1, 0xe000, 0x10);
+        assert_eq!(mmc1.cpu_read(0x6000), None);
+    }
+}
@@ -0,0 +1,261 @@
+use super::{Chr, Mapper, PrgRam};
+use crate::cartridge::{Cartridge, Mirroring, PROGRAM_RAM_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+/// A12 がこれだけの PPU サイクル low だった後の立ち上がりだけ数える
+/// (実機は M2 の立ち下がり 3 回分)
+const A12_LOW_FILTER: u64 = 10;
+
+/// # MMC3 (mapper 4, TxROM)
+/// 偶数アドレス / 奇数アドレスでレジスタが分かれる
+/// - $8000: bank select [CP.. .RRR], $8001: bank data
+/// - $A000: mirroring, $A001: PRG RAM protect
+/// - $C000: IRQ latch, $C001: IRQ reload
+/// - $E000: IRQ disable, $E001: IRQ enable
+pub struct Mmc3 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: PrgRam,
+    /// ヘッダで four screen のときはミラーリングを切り替えられない
+    four_screen: bool,
+
+    /// [CP.. .RRR]: u8
+    /// C: CHR A12 反転 (0: 2 KiB x 2 が $0000, 1: 2 KiB x 2 が $1000)
+    /// P: PRG バンクモード (0: $8000 を切り替え, 1: $C000 を切り替え)
+    /// RRR: 次に $8001 で書き込むバンクレジスタ
+    bank_select: u8,
+    /// R0-R7
+    /// R0, R1: 2 KiB CHR, R2-R5: 1 KiB CHR, R6, R7: 8 KiB PRG
+    banks: [u8; 8],
+    mirroring: Mirroring,
+    /// [RW.. ....]: u8
+    /// R: PRG RAM 有効, W: 書き込み禁止
+    prg_ram_protect: u8,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    /// 最後に A12 が high だった PPU サイクル
+    last_a12_high: u64,
+    a12: bool,
+}
+
+impl Mmc3 {
+    pub fn new(cartridge: Cartridge) -> Self {
+        let prg_ram = PrgRam::new(&cartridge, PROGRAM_RAM_SIZE);
+        let chr = Chr::new(&cartridge);
+        let four_screen = cartridge.header.mirroring == Mirroring::FourScreen;
+
+        Mmc3 {
+            prg_rom: cartridge.prg_rom,
+            chr,
+            prg_ram,
+            four_screen,
+            bank_select: 0,
+            banks: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring: cartridge.header.mirroring,
+            prg_ram_protect: 0x80,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            last_a12_high: 0,
+            a12: false,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        let second_last = banks.saturating_sub(2);
+        let bank = match (self.bank_select & 0x40 != 0, addr) {
+            (false, 0x8000..=0x9fff) => self.banks[6] as usize,
+            (true, 0x8000..=0x9fff) => second_last,
+            (_, 0xa000..=0xbfff) => self.banks[7] as usize,
+            (false, 0xc000..=0xdfff) => second_last,
+            (true, 0xc000..=0xdfff) => self.banks[6] as usize,
+            (_, _) => banks - 1,
+        };
+        ((bank % banks) * PRG_BANK_SIZE) | (addr as usize & 0x1fff)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        // C が立っていたら $0000 と $1000 を入れ替える
+        let addr = if self.bank_select & 0x80 != 0 { addr ^ 0x1000 } else { addr } as usize;
+        let bank = match addr {
+            0x0000..=0x07ff => (self.banks[0] & 0xfe) as usize | (addr >> 10 & 0x01),
+            0x0800..=0x0fff => (self.banks[1] & 0xfe) as usize | (addr >> 10 & 0x01),
+            _ => self.banks[2 + (addr - 0x1000) / CHR_BANK_SIZE] as usize,
+        };
+        bank * CHR_BANK_SIZE + (addr & 0x03ff)
+    }
+
+    /// A12 の立ち上がりでスキャンラインカウンタを進める
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff if self.prg_ram_protect & 0x80 != 0 => self.prg_ram.read(addr),
+            0x8000..=0xffff => Some(self.prg_rom[self.prg_offset(addr)]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match (addr, addr & 0x01) {
+            (0x6000..=0x7fff, _) => {
+                if self.prg_ram_protect & 0xc0 == 0x80 {
+                    self.prg_ram.write(addr, data);
+                }
+            }
+            (0x8000..=0x9fff, 0) => self.bank_select = data,
+            (0x8000..=0x9fff, _) => self.banks[(self.bank_select & 0x07) as usize] = data,
+            (0xa000..=0xbfff, 0) => {
+                if !self.four_screen {
+                    self.mirroring = if data & 0x01 == 0 {
+                        Mirroring::Vertical
+                    } else {
+                        Mirroring::Horizontal
+                    };
+                }
+            }
+            (0xa000..=0xbfff, _) => self.prg_ram_protect = data,
+            (0xc000..=0xdfff, 0) => self.irq_latch = data,
+            (0xc000..=0xdfff, _) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xe000..=0xffff, 0) => {
+                // 無効にすると保留中の IRQ も取り消される
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (0xe000..=0xffff, _) => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let offset = self.chr_offset(addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn notify_ppu_address(&mut self, addr: u16, ppu_cycle: u64) {
+        let a12 = addr & 0x1000 != 0;
+        if a12 {
+            if !self.a12 && ppu_cycle.saturating_sub(self.last_a12_high) >= A12_LOW_FILTER {
+                self.clock_irq_counter();
+            }
+            self.last_a12_high = ppu_cycle;
+        }
+        self.a12 = a12;
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn prg_ram(&self) -> Option<&PrgRam> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::ines_header;
+
+    fn mmc3() -> Mmc3 {
+        // PRG ROM 128 KiB, CHR ROM 8 KiB, 各バンクの中身はバンク番号
+        let mut binary = ines_header([8, 1, 0x40, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for bank in 0..16 {
+            binary.extend(vec![bank; PRG_BANK_SIZE]);
+        }
+        for bank in 0..8 {
+            binary.extend(vec![bank; CHR_BANK_SIZE]);
+        }
+        Mmc3::new(Cartridge::parse(&binary).unwrap())
+    }
+
+    #[test]
+    fn banking() {
+        let mut mmc3 = mmc3();
+        mmc3.cpu_write(0x8000, 6);
+        mmc3.cpu_write(0x8001, 3);
+        assert_eq!(mmc3.cpu_read(0x8000), Some(3));
+        assert_eq!(mmc3.cpu_read(0xc000), Some(14));
+        assert_eq!(mmc3.cpu_read(0xe000), Some(15));
+        // PRG モード 1
+        mmc3.cpu_write(0x8000, 0x40);
+        assert_eq!(mmc3.cpu_read(0x8000), Some(14));
+        assert_eq!(mmc3.cpu_read(0xc000), Some(3));
+
+        // R2 = 5 を CHR A12 反転で $0000 に
+        mmc3.cpu_write(0x8000, 0x82);
+        mmc3.cpu_write(0x8001, 5);
+        assert_eq!(mmc3.ppu_read(0x0000), 5);
+        mmc3.cpu_write(0xa000, 1);
+        assert_eq!(mmc3.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn scanline_irq() {
+        let mut mmc3 = mmc3();
+        mmc3.cpu_write(0xc000, 2);
+        mmc3.cpu_write(0xc001, 0);
+        mmc3.cpu_write(0xe001, 0);
+        let mut cycle = 0;
+        let mut scanline = |mmc3: &mut Mmc3| {
+            // BG は $0000, スプライトは $1000 から読む
+            mmc3.notify_ppu_address(0x0000, cycle);
+            mmc3.notify_ppu_address(0x1000, cycle + 260);
+            // スプライトのフェッチの間の短い low は数えない
+            mmc3.notify_ppu_address(0x2000, cycle + 262);
+            mmc3.notify_ppu_address(0x1000, cycle + 264);
+            cycle += 341;
+        };
+        scanline(&mut mmc3);
+        assert!(!mmc3.irq());
+        scanline(&mut mmc3);
+        assert!(!mmc3.irq());
+        scanline(&mut mmc3);
+        assert!(mmc3.irq());
+        mmc3.cpu_write(0xe000, 0);
+        assert!(!mmc3.irq());
+    }
+}
@@ -0,0 +1,116 @@
+use super::{Chr, Mapper, PrgRam};
+use crate::cartridge::{Cartridge, Mirroring, CHARACTER_ROM_SIZE, PROGRAM_ROM_SIZE};
+use crate::error::{NesError, Result};
+
+/// # NROM (mapper 0)
+/// バンク切り替えなし
+/// - NROM-128: PRG ROM 16 KiB, $C000-$FFFF は $8000-$BFFF のミラー
+/// - NROM-256: PRG ROM 32 KiB
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    /// CHR ROM もしくは CHR RAM (8 KiB)
+    chr: Chr,
+    /// Family BASIC などは $6000-$7FFF に RAM を持つ
+    prg_ram: PrgRam,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(cartridge: Cartridge) -> Result<Self> {
+        let prg_ram = PrgRam::new(&cartridge, 0);
+        let prg_size = cartridge.prg_rom.len();
+        if prg_size != PROGRAM_ROM_SIZE && prg_size != 2 * PROGRAM_ROM_SIZE {
+            return Err(NesError::InvalidHeader("NROM PRG ROM must be 16 KiB or 32 KiB"));
+        }
+        let chr = Chr::new(&cartridge);
+        if chr.len() != CHARACTER_ROM_SIZE {
+            return Err(NesError::InvalidHeader("NROM CHR ROM must be 8 KiB"));
+        }
+
+        Ok(Nrom {
+            prg_ram,
+            prg_rom: cartridge.prg_rom,
+            chr,
+            mirroring: cartridge.header.mirroring,
+        })
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff => self.prg_ram.read(addr),
+            // NROM-128 は 16 KiB ごとにミラーされる
+            0x8000..=0xffff => Some(self.prg_rom[(addr as usize - 0x8000) % self.prg_rom.len()]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        // ROM は書き込めないので無視される
+        if let 0x6000..=0x7fff = addr {
+            self.prg_ram.write(addr, data);
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(addr as usize & 0x1fff)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize & 0x1fff, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn prg_ram(&self) -> Option<&PrgRam> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{ines_header, Cartridge};
+
+    fn cartridge(prg_pages: u8) -> Cartridge {
+        let mut binary = ines_header([prg_pages, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for page in 0..prg_pages {
+            binary.extend(vec![page; PROGRAM_ROM_SIZE]);
+        }
+        binary.extend(vec![0xcc; CHARACTER_ROM_SIZE]);
+        Cartridge::parse(&binary).unwrap()
+    }
+
+    #[test]
+    fn nrom_128_is_mirrored() {
+        let mut nrom = Nrom::new(cartridge(1)).unwrap();
+        assert_eq!(nrom.cpu_read(0x8000), Some(0));
+        assert_eq!(nrom.cpu_read(0xfffc), Some(0));
+        assert_eq!(nrom.cpu_read(0x5000), None);
+    }
+
+    #[test]
+    fn nrom_256() {
+        let mut nrom = Nrom::new(cartridge(2)).unwrap();
+        assert_eq!(nrom.cpu_read(0xbfff), Some(0));
+        assert_eq!(nrom.cpu_read(0xc000), Some(1));
+        // CHR ROM は書き込めない
+        nrom.ppu_write(0x0000, 0x00);
+        assert_eq!(nrom.ppu_read(0x0000), 0xcc);
+    }
+}
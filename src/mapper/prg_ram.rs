@@ -0,0 +1,82 @@
+use crate::cartridge::{Cartridge, PROGRAM_RAM_SIZE, TRAINER_SIZE};
+
+/// カートリッジ上の $6000-$7FFF の PRG RAM
+/// バッテリーバックアップされていれば .sav として保存できる
+pub struct PrgRam {
+    memory: Vec<u8>,
+    battery: bool,
+}
+
+impl PrgRam {
+    /// ヘッダのサイズで確保する
+    /// min_size はマッパーが必ず持っている RAM のサイズ (MMC1, MMC3 は 8 KiB)
+    /// トレーナがあるときは $6000-$7FFF 全体 (8 KiB) を確保する
+    pub fn new(cartridge: &Cartridge, min_size: usize) -> Self {
+        let header = &cartridge.header;
+        let min_size = if cartridge.trainer.is_some() {
+            std::cmp::max(min_size, PROGRAM_RAM_SIZE)
+        } else {
+            min_size
+        };
+        let size = std::cmp::max(header.prg_ram_size + header.prg_nvram_size, min_size);
+        let mut memory = vec![0; size];
+        // トレーナは $7000-$71FF に置く
+        if let Some(trainer) = &cartridge.trainer {
+            memory[0x1000..0x1000 + TRAINER_SIZE].copy_from_slice(trainer);
+        }
+        PrgRam {
+            memory,
+            battery: header.battery,
+        }
+    }
+
+    /// RAM がなければ None (open bus)
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        if self.memory.is_empty() {
+            return None;
+        }
+        Some(self.memory[(addr as usize - 0x6000) % self.memory.len()])
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        if self.memory.is_empty() {
+            return;
+        }
+        let len = self.memory.len();
+        self.memory[(addr as usize - 0x6000) % len] = data;
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{ines_header, CHARACTER_ROM_SIZE, PROGRAM_ROM_SIZE};
+
+    #[test]
+    fn trainer_without_prg_ram() {
+        // NES 2.0, PRG RAM 0 byte, トレーナあり
+        let mut binary = ines_header([1, 1, 0x04, 0x08, 0, 0, 0, 0, 0, 0, 0, 0]);
+        binary.extend(vec![0x42; TRAINER_SIZE]);
+        binary.extend(vec![0; PROGRAM_ROM_SIZE + CHARACTER_ROM_SIZE]);
+        let cartridge = Cartridge::parse(&binary).unwrap();
+        assert_eq!(cartridge.header.prg_ram_size, 0);
+
+        let ram = PrgRam::new(&cartridge, 0);
+        assert_eq!(ram.as_slice().len(), PROGRAM_RAM_SIZE);
+        assert_eq!(ram.read(0x7000), Some(0x42));
+        assert_eq!(ram.read(0x71ff), Some(0x42));
+        assert_eq!(ram.read(0x7200), Some(0x00));
+    }
+}
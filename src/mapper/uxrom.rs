@@ -0,0 +1,122 @@
+use super::{Chr, Mapper, PrgRam};
+use crate::cartridge::{Cartridge, Mirroring};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// # UxROM (mapper 2)
+/// - $8000-$BFFF: 16 KiB 切り替え
+/// - $C000-$FFFF: 最後のバンクに固定
+///
+/// $8000-$FFFF への書き込みでバンクを選ぶ
+pub struct Uxrom {
+    prg_rom: Vec<u8>,
+    /// CHR RAM のものがほとんど
+    chr: Chr,
+    prg_ram: PrgRam,
+    mirroring: Mirroring,
+    prg_bank: u8,
+}
+
+impl Uxrom {
+    pub fn new(cartridge: Cartridge) -> Self {
+        let prg_ram = PrgRam::new(&cartridge, 0);
+        let chr = Chr::new(&cartridge);
+
+        Uxrom {
+            prg_ram,
+            prg_rom: cartridge.prg_rom,
+            chr,
+            mirroring: cartridge.header.mirroring,
+            prg_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Uxrom {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        let bank = match addr {
+            0x6000..=0x7fff => return self.prg_ram.read(addr),
+            0x8000..=0xbfff => self.prg_bank as usize,
+            0xc000..=0xffff => self.prg_rom.len() / PRG_BANK_SIZE - 1,
+            _ => return None,
+        };
+        let offset = bank * PRG_BANK_SIZE + (addr as usize & 0x3fff);
+        Some(self.prg_rom[offset % self.prg_rom.len()])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff => self.prg_ram.write(addr, data),
+            // バス競合: 同じアドレスの ROM の値と AND される
+            0x8000..=0xffff => self.prg_bank = data & self.cpu_read(addr).unwrap_or(0xff),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(addr as usize & 0x1fff)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize & 0x1fff, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn prg_ram(&self) -> Option<&PrgRam> {
+        Some(&self.prg_ram)
+    }
+
+    fn prg_ram_mut(&mut self) -> Option<&mut PrgRam> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{ines_header, PROGRAM_ROM_SIZE};
+
+    /// PRG ROM 128 KiB, 各バンクの中身はバンク番号
+    fn uxrom() -> Uxrom {
+        let mut binary = ines_header([8, 0, 0x20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for bank in 0..8 {
+            binary.extend(vec![bank; PROGRAM_ROM_SIZE]);
+        }
+        Uxrom::new(Cartridge::parse(&binary).unwrap())
+    }
+
+    #[test]
+    fn prg_banking() {
+        let mut uxrom = uxrom();
+        assert_eq!(uxrom.cpu_read(0x8000), Some(0));
+        assert_eq!(uxrom.cpu_read(0xc000), Some(7));
+        // 固定バンク (7) の上に書き込めば値がそのまま通る
+        uxrom.cpu_write(0xc000, 5);
+        assert_eq!(uxrom.cpu_read(0xbfff), Some(5));
+        assert_eq!(uxrom.cpu_read(0xfffe), Some(7));
+        // CHR RAM
+        uxrom.ppu_write(0x1234, 0xab);
+        assert_eq!(uxrom.ppu_read(0x1234), 0xab);
+    }
+
+    #[test]
+    fn bus_conflict() {
+        let mut uxrom = uxrom();
+        uxrom.cpu_write(0xc000, 6);
+        assert_eq!(uxrom.cpu_read(0x8000), Some(6));
+        // バンク 6 の上では 5 & 6 = 4 になる
+        uxrom.cpu_write(0x8000, 5);
+        assert_eq!(uxrom.cpu_read(0x8000), Some(4));
+    }
+}
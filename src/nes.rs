@@ -1,64 +1,238 @@
+use crate::apu;
+use crate::cartridge;
 use crate::cpu;
 use crate::cpu_bus;
+use crate::error::{NesError, Result};
+use crate::input::{
+    self, Arkanoid, ArkanoidState, ButtonState, FourPlayerAdapter, InputDevice, Keyboard, KeyboardState,
+    PowerPad, PowerPadState, Zapper, ZapperState,
+};
+use crate::mapper;
 use crate::ppu;
 use crate::screen;
 use crate::wram;
 use std::cell::RefCell;
 use std::fs::File;
 use std::io::prelude::*;
-use std::rc::{Rc, Weak};
-
-const NES_HEADER_SIZE: usize = 0x0010;
-const PROGRAM_ROM_SIZE: usize = 0x4000;
-const CHARACTER_ROM_SIZE: usize = 0x2000;
+use std::rc::Rc;
 
 use web_sys;
 
 pub struct NES {
     cpu: Rc<RefCell<cpu::Cpu>>,
     pub ppu: Rc<RefCell<ppu::Ppu>>,
+    apu: Rc<RefCell<apu::Apu>>,
+    input: Rc<RefCell<input::ControllerPorts>>,
+    mapper: mapper::SharedMapper,
 }
 
 /// CPUのクロック数の管理やppuのクロック数の管理をする
 impl NES {
-    pub fn new(file: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(file: &str) -> Result<Self> {
         let mut f = File::open(file)?;
         let mut program: Vec<u8> = Vec::new();
         f.read_to_end(&mut program)?;
-        let (prog, _) = NES::parse(program).unwrap();
+        NES::load(program)
+    }
 
-        dbg!(&prog[..20]);
+    pub fn load(program: Vec<u8>) -> Result<Self> {
+        let cartridge = NES::parse(program)?;
+        NES::from_cartridge(cartridge)
+    }
 
+    fn from_cartridge(cartridge: cartridge::Cartridge) -> Result<Self> {
+        // カートリッジの基板はcpuとppuの両方からつながる
+        let mapper = Rc::new(RefCell::new(mapper::new(cartridge)?));
         // wramの初期化
         let wram = wram::WRAM::new();
         // ppuの初期化
         let screen = screen::Screen::new();
-        let ppu = Rc::new(RefCell::new(ppu::Ppu::new(screen, Weak::new())));
+        let ppu = Rc::new(RefCell::new(ppu::Ppu::new(screen, mapper.clone())));
 
-        let cpu_bus = cpu_bus::CpuBus::new(wram, ppu.clone(), prog);
-        let mut cpu = Rc::new(RefCell::new(cpu::Cpu::new(cpu_bus)));
-        ppu.borrow_mut().add_cpu(cpu.clone());
+        let apu = Rc::new(RefCell::new(apu::Apu::new()));
+        let input = Rc::new(RefCell::new(input::ControllerPorts::new()));
 
-        Ok(NES { cpu, ppu })
+        // ppu と apu は cpu_bus が cpu と同期させながら進める
+        let cpu_bus = cpu_bus::CpuBus::new(wram, ppu.clone(), apu.clone(), input.clone(), mapper.clone());
+        let cpu = Rc::new(RefCell::new(cpu::Cpu::new(cpu_bus)));
+
+        Ok(NES {
+            cpu,
+            ppu,
+            apu,
+            input,
+            mapper,
+        })
     }
 
-    pub fn load(program: Vec<u8>) -> Self {
-        let (prog, chrs) = NES::parse(program).unwrap();
+    /// # save_data
+    /// バッテリーバックアップされた PRG RAM の中身 (.sav)
+    /// バッテリーがないカートリッジでは None
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        let mapper = self.mapper.borrow();
+        mapper
+            .prg_ram()
+            .filter(|ram| ram.has_battery())
+            .map(|ram| ram.as_slice().to_vec())
+    }
 
-        dbg!(&prog[..20]);
+    /// # load_save_data
+    /// .sav の中身を PRG RAM に書き戻す
+    pub fn load_save_data(&mut self, data: &[u8]) -> Result<()> {
+        let mut mapper = self.mapper.borrow_mut();
+        let ram = match mapper.prg_ram_mut() {
+            Some(ram) if ram.has_battery() => ram,
+            _ => return Err(NesError::NoBatteryRam),
+        };
+        let memory = ram.as_mut_slice();
+        if memory.len() != data.len() {
+            return Err(NesError::SaveSizeMismatch {
+                expected: memory.len(),
+                actual: data.len(),
+            });
+        }
+        memory.copy_from_slice(data);
+        Ok(())
+    }
 
-        // wramの初期化
-        let wram = wram::WRAM::new();
-        // ppuの初期化
-        let screen = screen::Screen::new();
-        let ppu = Rc::new(RefCell::new(ppu::Ppu::new(screen, Weak::new())));
+    /// PRG RAM を .sav ファイルに書き出す
+    /// バッテリーがないカートリッジでは何もしない
+    pub fn save_to_file(&self, file: &str) -> Result<()> {
+        if let Some(data) = self.save_data() {
+            let mut f = File::create(file)?;
+            f.write_all(&data)?;
+        }
+        Ok(())
+    }
 
-        let cpu_bus = cpu_bus::CpuBus::new(wram, ppu.clone(), prog);
-        let mut cpu = Rc::new(RefCell::new(cpu::Cpu::new(cpu_bus)));
-        ppu.borrow_mut().add_cpu(cpu.clone());
-        ppu.borrow_mut().load_pattern_table(chrs);
+    /// .sav ファイルを PRG RAM に読み込む
+    pub fn load_save_file(&mut self, file: &str) -> Result<()> {
+        let mut f = File::open(file)?;
+        let mut data: Vec<u8> = Vec::new();
+        f.read_to_end(&mut data)?;
+        self.load_save_data(&data)
+    }
+
+    /// # pattern_memory
+    /// パターンテーブルのメモリ (CHR ROM / CHR RAM) 全体
+    /// デバッガでパターンを表示するのに使う
+    pub fn pattern_memory(&self) -> Vec<u8> {
+        self.mapper.borrow().chr().as_slice().to_vec()
+    }
 
-        NES { cpu, ppu }
+    /// # chr_ram
+    /// CHR RAM の中身, ステートの保存用
+    /// CHR ROM のカートリッジでは None
+    pub fn chr_ram(&self) -> Option<Vec<u8>> {
+        let mapper = self.mapper.borrow();
+        Some(mapper.chr())
+            .filter(|chr| chr.is_ram())
+            .map(|chr| chr.as_slice().to_vec())
+    }
+
+    /// # load_chr_ram
+    /// chr_ram で取り出した中身を書き戻す
+    pub fn load_chr_ram(&mut self, data: &[u8]) -> Result<()> {
+        let mut mapper = self.mapper.borrow_mut();
+        let chr = mapper.chr_mut();
+        if !chr.is_ram() {
+            return Err(NesError::NoChrRam);
+        }
+        let memory = chr.as_mut_slice();
+        if memory.len() != data.len() {
+            return Err(NesError::SaveSizeMismatch {
+                expected: memory.len(),
+                actual: data.len(),
+            });
+        }
+        memory.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// # set_sample_rate
+    /// drain_audio で取り出す音声のサンプリング周波数 (初期値は 44.1 kHz)
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.apu.borrow_mut().set_sample_rate(sample_rate);
+    }
+
+    /// # drain_audio
+    /// たまっている音声 (モノラル, f32) を out の先頭から詰める
+    /// 0.5 秒分を超えてたまると古いものから捨てられるので, フロントエンドはこまめに呼ぶ
+    /// # Return
+    /// 書き込んだサンプル数
+    pub fn drain_audio(&mut self, out: &mut [f32]) -> usize {
+        self.apu.borrow_mut().drain_audio(out)
+    }
+
+    /// # set_buttons
+    /// port: 0 (1P) か 1 (2P) の標準コントローラのボタンを押す / 離す
+    /// 4 人用のアダプタをつないでいれば 2 (3P), 3 (4P) も使える
+    /// 次にゲームがストローブしたときに反映される
+    pub fn set_buttons(&mut self, port: usize, buttons: ButtonState) {
+        self.input.borrow_mut().set_buttons(port, buttons);
+    }
+
+    /// # connect_four_player
+    /// 4 人用のアダプタ (Four Score かファミコンの拡張端子のもの) をつなぐ
+    pub fn connect_four_player(&mut self, adapter: FourPlayerAdapter) {
+        self.input.borrow_mut().connect_four_player(adapter);
+    }
+
+    /// # connect_device
+    /// port: 0 (1P) か 1 (2P) のコントローラポートに機器をつなぐ, それ以外は無視する
+    /// 例: nes.connect_device(1, Box::new(PowerPad::new()))
+    pub fn connect_device(&mut self, port: usize, device: Box<dyn InputDevice>) {
+        self.input.borrow_mut().connect(port, device);
+    }
+
+    /// # connect_expansion_device
+    /// ファミコンの拡張端子に機器をつなぐ, None なら外す
+    /// 例: nes.connect_expansion_device(Some(Box::new(Keyboard::new())))
+    pub fn connect_expansion_device(&mut self, device: Option<Box<dyn InputDevice>>) {
+        self.input.borrow_mut().connect_expansion(device);
+    }
+
+    /// # connect_zapper
+    /// 2P 側のポートに Zapper をつなぐ
+    pub fn connect_zapper(&mut self) {
+        let zapper = Zapper::new(self.ppu.clone());
+        self.input.borrow_mut().connect(1, Box::new(zapper));
+    }
+
+    /// # set_zapper
+    /// ポインタの位置 (画面の座標) とトリガーを渡す
+    /// Zapper がつながっていなければ何もしない
+    pub fn set_zapper(&mut self, state: ZapperState) {
+        if let Some(zapper) = self.input.borrow_mut().device_mut::<Zapper>(1) {
+            zapper.set_state(state);
+        }
+    }
+
+    /// # set_arkanoid
+    /// アルカノイドのコントローラ (2P 側のポートか拡張端子) のつまみとボタンを渡す
+    pub fn set_arkanoid(&mut self, state: ArkanoidState) {
+        let mut input = self.input.borrow_mut();
+        if let Some(vaus) = input.device_mut::<Arkanoid>(1) {
+            vaus.set_state(state);
+        } else if let Some(vaus) = input.expansion_mut::<Arkanoid>() {
+            vaus.set_state(state);
+        }
+    }
+
+    /// # set_power_pad
+    /// 2P 側のポートのパワーパッドのボタンを渡す
+    pub fn set_power_pad(&mut self, state: PowerPadState) {
+        if let Some(pad) = self.input.borrow_mut().device_mut::<PowerPad>(1) {
+            pad.set_state(state);
+        }
+    }
+
+    /// # set_keyboard
+    /// 拡張端子のファミリーベーシックのキーボードのキーを渡す
+    pub fn set_keyboard(&mut self, state: KeyboardState) {
+        if let Some(keyboard) = self.input.borrow_mut().expansion_mut::<Keyboard>() {
+            keyboard.set_state(state);
+        }
     }
 
     /// # next
@@ -67,9 +241,9 @@ impl NES {
     /// cpuが何サイクル使ったか
     pub fn next(&mut self) -> usize {
         // cycles: cpuが何サイクル回ったか
+        // ppu は cpu_bus が 3 倍のサイクル数, apu は同じサイクル数進める
         let mut cycles: usize = 0;
         cycles += self.cpu.borrow_mut().run() as usize;
-        self.ppu.borrow_mut().run(3 * cycles);
         return cycles;
     }
 
@@ -80,43 +254,123 @@ impl NES {
         let hz = 179_000u32 / 6;
         // let hz = 30u32; // ヘルツ
         loop {
-            // cycles: cpuが何サイクル回ったか
-            let mut cycles: usize = 0;
-
-            // cpu実行
-            cycles += self.cpu.borrow_mut().run() as usize;
-            // ppu 実行
-            self.ppu.borrow_mut().run(3 * cycles);
+            // cpu実行, ppu と apu も一緒に進む
+            self.cpu.borrow_mut().run();
             // 1ナノ秒 = 0.000 000 001 秒
             std::thread::sleep(std::time::Duration::new(0, 1_000_000_000 / hz));
         }
     }
 
-    /// nesのバイナリをprogramROMとcharactorROMにパースする
-    fn parse(binary: Vec<u8>) -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
-        if !(&binary[0..4] == &[0x4e, 0x45, 0x53, 0x1a]) {
-            // FIXME: errorを返すようにする
-            panic!("not nes file");
-        }
-        let program_rom_page = binary[4];
-        // TODO: logの方法を考える
-        // TODO: ちゃんとぱーす考える
-        //   log.info('prom pages =', programROMPages);
-        let character_rom_page = binary[5];
-        //   log.info('crom pages =', characterROMPages);
-        //   const isHorizontalMirror = !(nes[6] & 0x01);
-        //   const mapper = (((nes[6] & 0xF0) >> 4) | nes[7] & 0xF0);
-        //   log.info('mapper', mapper);
-        let character_rom_start = NES_HEADER_SIZE + program_rom_page as usize * PROGRAM_ROM_SIZE;
-        let character_rom_end = character_rom_start + character_rom_page as usize * CHARACTER_ROM_SIZE;
-        let prog = binary[NES_HEADER_SIZE..character_rom_start].to_vec();
-        // TODO: progの長さが0x8000以下だと割り込みベクタがかけることになるのでおかしくなるかも
-        Ok((
-            prog,
-            binary[character_rom_start..character_rom_end].to_vec(),
-        ))
+    /// nesのバイナリをヘッダ, programROM, charactorROMにパースする
+    fn parse(binary: Vec<u8>) -> Result<cartridge::Cartridge> {
+        cartridge::Cartridge::parse(&binary)
     }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::cartridge::ines_header;
+
+    fn mmc1(battery: bool) -> NES {
+        let flags6 = if battery { 0x12 } else { 0x10 };
+        let mut binary = ines_header([2, 1, flags6, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        binary.extend(vec![0; 2 * cartridge::PROGRAM_ROM_SIZE]);
+        binary.extend(vec![0; cartridge::CHARACTER_ROM_SIZE]);
+        NES::load(binary).unwrap()
+    }
+
+    #[test]
+    fn save_data() {
+        let mut nes = mmc1(true);
+        let mut data = vec![0; cartridge::PROGRAM_RAM_SIZE];
+        data[0] = 0x42;
+        nes.load_save_data(&data).unwrap();
+        assert_eq!(nes.mapper.borrow_mut().cpu_read(0x6000), Some(0x42));
+        assert_eq!(nes.save_data(), Some(data));
+        assert!(matches!(
+            nes.load_save_data(&[0; 4]),
+            Err(NesError::SaveSizeMismatch { .. })
+        ));
+
+        let mut nes = mmc1(false);
+        assert_eq!(nes.save_data(), None);
+        assert!(matches!(nes.load_save_data(&[0; 4]), Err(NesError::NoBatteryRam)));
+    }
+
+    #[test]
+    fn chr_ram_state() {
+        // UNROM, CHR ROM 0 ページ
+        let mut binary = ines_header([2, 0, 0x20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        binary.extend(vec![0; 2 * cartridge::PROGRAM_ROM_SIZE]);
+        let mut nes = NES::load(binary).unwrap();
+        let mut data = vec![0; cartridge::CHARACTER_ROM_SIZE];
+        data[0x1234] = 0xab;
+        nes.load_chr_ram(&data).unwrap();
+        assert_eq!(nes.mapper.borrow_mut().ppu_read(0x1234), 0xab);
+        assert_eq!(nes.chr_ram(), Some(data.clone()));
+        assert_eq!(nes.pattern_memory(), data);
+        assert!(matches!(
+            nes.load_chr_ram(&[0; 4]),
+            Err(NesError::SaveSizeMismatch { .. })
+        ));
+
+        // CHR ROM は表示できるが書き戻せない
+        let mut nes = mmc1(false);
+        assert_eq!(nes.chr_ram(), None);
+        assert_eq!(nes.pattern_memory().len(), cartridge::CHARACTER_ROM_SIZE);
+        assert!(matches!(nes.load_chr_ram(&[0; 4]), Err(NesError::NoChrRam)));
+    }
+
+    /// 次にビームが line に来るまで進める
+    fn run_until_line(nes: &mut NES, line: usize) {
+        while nes.ppu.borrow().beam().0 == line {
+            nes.next();
+        }
+        while nes.ppu.borrow().beam().0 != line {
+            nes.next();
+        }
+    }
+
+    #[test]
+    fn zapper() {
+        let mut nes = mmc1(false);
+        nes.connect_zapper();
+        // 描画を止めているので画面全体が背景色の白
+        nes.ppu.borrow_mut().write_vram(0x3f00, 0x30);
+        run_until_line(&mut nes, 100);
+        let mut read = |state| {
+            nes.set_zapper(state);
+            nes.input.borrow_mut().read(1)
+        };
+        // 描いてすぐのところは光っている
+        assert_eq!(read(ZapperState { aim: Some((128, 90)), trigger: true }), 0x10);
+        // まだ描いていないところ, 描いてから時間が経ったところ, 画面の外
+        assert_eq!(read(ZapperState { aim: Some((128, 120)), trigger: false }), 0x08);
+        assert_eq!(read(ZapperState { aim: Some((128, 10)), trigger: false }), 0x08);
+        assert_eq!(read(ZapperState { aim: None, trigger: false }), 0x08);
+
+        // 黒は光らない
+        nes.ppu.borrow_mut().write_vram(0x3f00, 0x0f);
+        run_until_line(&mut nes, 100);
+        nes.set_zapper(ZapperState { aim: Some((128, 90)), trigger: false });
+        assert_eq!(nes.input.borrow_mut().read(1), 0x08);
+    }
+
+    #[test]
+    fn drain_audio() {
+        let mut nes = mmc1(false);
+        nes.set_sample_rate(48000.0);
+        // 1 フレーム (約 1/60 秒) 分
+        let mut cycles = 0;
+        while cycles < 29781 {
+            cycles += nes.next();
+        }
+        let mut out = vec![1.0; 2048];
+        let len = nes.drain_audio(&mut out);
+        assert!((790..=810).contains(&len), "len: {}", len);
+        // 電源投入時の三角波の直流成分はハイパスで抜けて無音になる
+        assert!(out[len - 1].abs() < 1e-3, "{}", out[len - 1]);
+        assert_eq!(out[len], 1.0);
+    }
+}
@@ -1,41 +1,95 @@
-use crate::cpu;
+use crate::cartridge::Mirroring;
+use crate::mapper;
 use crate::screen;
 use crate::screen::{INTERNAL_SIZE, SCREEN_SIZE};
-use std::cell::RefCell;
 use std::fmt::{self, Debug};
 use std::ops::Index;
-use std::rc::{Rc, Weak};
-use web_sys::console::log_1;
-use itertools::izip;
 
 pub struct PpuBus {
     pub screen: screen::Screen,
-    cpu: Weak<RefCell<cpu::Cpu>>,
+    /// パターンテーブル $0000-$1FFF はカートリッジ側
+    mapper: mapper::SharedMapper,
 }
 
 impl PpuBus {
-    pub fn new(screen: screen::Screen, cpu: Weak<RefCell<cpu::Cpu>>) -> Self {
-        PpuBus { screen, cpu }
+    pub fn new(screen: screen::Screen, mapper: mapper::SharedMapper) -> Self {
+        PpuBus { screen, mapper }
     }
 }
 
 pub struct Ppu {
     pub register: Register,
 
-    /// buffer
-    /// 2回書き込見済みであればtrue
-    /// 1回しか書いてないならfalse
-    buffer_2006: (u16, bool),
+    /// 内部レジスタ (loopy)
+    /// v, t: [.yyy NNYY YYYX XXXX]: u16
+    /// y: fine Y, N: ネームテーブル, Y: coarse Y, X: coarse X
+    /// 描画中は v が今読んでいるタイルを指す, t は次のフレーム/ラインの先頭
+    v: u16,
+    t: u16,
+    /// fine X (0-7)
+    x: u8,
+    /// $2005/$2006 の 1 回目の書き込みなら false, 2 回目なら true
+    /// $2002 を読むとリセットされる
+    w: bool,
+    /// $2007 の読み込みバッファ
+    /// パレット以外は 1 回前に読んだ値が返る
+    read_buffer: u8,
+    /// 最後にレジスタに書き込まれた値
+    /// 書き込み専用レジスタを読むとこれが返る
+    latch: u8,
 
     /// ppu bus
     pub ppu_bus: PpuBus,
+    /// ネームテーブル $2000-$2FFF の実体
+    /// 前半 2 KiB が本体の CIRAM, 後半 2 KiB は four screen のカートリッジが持つ VRAM
+    nametables: Vec<u8>,
+    /// パレット $3F00-$3F1F
     /// パレットにはNESの色ID
     /// [00VVHHHH]: u8
     /// V: 明度
     /// H: 色相
-    vram: Vec<u8>,
-    /// cpuの341サイクルごとに1周する
+    palette: [u8; 0x20],
+    /// OAM, 4 byte x 64 スプライト
+    oam: [u8; 0x100],
+    /// 次のラインに表示するスプライト (最大 8 個)
+    /// 手前のラインの dot 257 で OAM から選ぶ
+    secondary_oam: Vec<Sprite>,
+    /// secondary OAM の先頭が OAM の 0 番のスプライトか
+    sprite0_in_line: bool,
+    /// secondary OAM のスプライトのパターン (low, high)
+    /// dot 257-320 で読む
+    sprite_patterns: Vec<(u8, u8)>,
+
+    /// BG のフェッチ中のタイル
+    /// dot 8 個ごとにネームテーブル, 属性, パターン low, high の順に読んでシフトレジスタに入れる
+    bg_next_tile: u8,
+    /// 2bit のパレット番号
+    bg_next_attr: u8,
+    bg_next_low: u8,
+    bg_next_high: u8,
+    /// BG のシフトレジスタ, 上位 8bit が今描いているタイル, 下位 8bit が次のタイル
+    /// 1 ドットごとに左に 1 つずらして bit 15 - fine X を出力する
+    bg_shift_low: u16,
+    bg_shift_high: u16,
+    /// パレット番号をパターンと同じようにずらせるよう 8bit に広げたもの
+    attr_shift_low: u16,
+    attr_shift_high: u16,
+
+    /// 描画中のフレーム, 1 ドットずつ埋めていく
+    /// [.... ...E EECC CCCC]: u16
+    /// E: PPUMASK の色強調 (BGR), C: NES の色 ID
+    frame: Vec<Vec<u16>>,
+    /// 今のラインの何ドット目か (0-340)
     cycles: usize,
+    /// $2002 が VBLANK の立つ直前に読まれた
+    suppress_vblank: bool,
+    /// NMI 出力の立ち上がりを CPU が拾うまで覚えておく
+    /// 後から $2002 を読んでも残るが, 立ったドットとその次のドットで読むと消える
+    nmi_edge: bool,
+    /// 奇数フレームでは描画が有効なら pre-render line の最後の 1 ドットを飛ばす
+    odd_frame: bool,
+    /// 電源投入からのサイクル数
+    clocks: u64,
     /// 現在何行目か
     lines: usize,
     /// フレーム数
@@ -79,22 +133,9 @@ pub struct Register {
     /// - aaaa aaaa
     /// - OAM read/write address
     pub oamaddr: u8,
-    /// - $2004
-    /// - dddd dddd
-    /// - OAM data read/write
-    pub oamdata: u8,
-    /// - $2005
-    /// - xxxx xxxx
-    /// - fine scroll position (two writes: X scroll, Y scroll)
-    pub ppuscroll: u8,
-    /// - $2006
-    /// - aaaa aaaa
-    /// - Ppu read/write address (two writes: most significant byte, least significant byte)
-    pub ppuaddr: u8,
-    /// - $2007
-    /// - dddd dddd
-    /// - Ppu data read/write
-    pub ppudata: u8,
+    // $2004 (OAMDATA) は oamaddr の指す OAM を読み書きする
+    // $2005 (PPUSCROLL) と $2006 (PPUADDR) は Ppu の v, t, x, w に書き込まれる
+    // $2007 (PPUDATA) は v の指す vram を読み書きする
 }
 
 impl Debug for Register {
@@ -107,24 +148,17 @@ impl Debug for Register {
         ppumask: {:08b}
         ppustatus: {:08b}
         oamaddr: {:08b}
-        oamdata: {:08b}
-        ppuscroll: {:08b}
-        ppuaddr: {:08b}
-        ppudata: {:08b}
         "#,
             self.ppuctrl,
             self.ppumask,
             self.ppustatus,
             self.oamaddr,
-            self.oamdata,
-            self.ppuscroll,
-            self.ppuaddr,
-            self.ppudata,
         )
     }
 }
 
-/// スプライトはSprite構造体64個の配列
+/// OAM の 4 byte が 1 つのスプライト, OAM には 64 個入る
+#[derive(Clone, Copy)]
 pub struct Sprite {
     /// y座標, +1されて表示, 0 < y < 240
     y: u8,
@@ -140,47 +174,99 @@ pub struct Sprite {
     x: u8,
 }
 
+impl Sprite {
+    fn new(oam: &[u8]) -> Self {
+        Sprite {
+            y: oam[0],
+            tile: oam[1],
+            attr: oam[2],
+            x: oam[3],
+        }
+    }
+
+    /// row 行目 (0 始まり) のパターンのアドレス
+    /// height が 16 のときはタイル ID の bit 0 でパターンテーブルを選ぶ
+    fn pattern_addr(&self, row: u16, height: u16, table: u16) -> u16 {
+        // 垂直反転
+        let row = if self.attr & 0x80 != 0 { height - 1 - row } else { row };
+        let (table, tile) = if height == 16 {
+            let table = (self.tile as u16 & 0x01) * 0x1000;
+            (table, (self.tile as u16 & 0xfe) + row / 8)
+        } else {
+            (table, self.tile as u16)
+        };
+        table + tile * 16 + row % 8
+    }
+}
+
 /// Ppuの描画要素
 /// - BG: 8x8のタイルを敷き詰めた画像、スクロールは出来るが制約有り
 ///
 /// - スプライト: 8x8 or 8x16 で最大64個
 impl Ppu {
-    pub fn new(screen: screen::Screen, cpu: Weak<RefCell<cpu::Cpu>>) -> Ppu {
+    pub fn new(screen: screen::Screen, mapper: mapper::SharedMapper) -> Ppu {
         Ppu {
             register: Register {
                 ppuctrl: 0,
                 ppumask: 0,
                 ppustatus: 0,
                 oamaddr: 0,
-                oamdata: 0,
-                ppuscroll: 0,
-                ppuaddr: 0,
-                ppudata: 0,
             },
-            ppu_bus: PpuBus::new(screen, cpu),
-            vram: vec![0; 0x4000],
+            ppu_bus: PpuBus::new(screen, mapper),
+            nametables: vec![0; 0x1000],
+            palette: [0; 0x20],
+            oam: [0; 0x100],
+            secondary_oam: Vec::with_capacity(8),
+            sprite0_in_line: false,
+            sprite_patterns: Vec::with_capacity(8),
+            bg_next_tile: 0,
+            bg_next_attr: 0,
+            bg_next_low: 0,
+            bg_next_high: 0,
+            bg_shift_low: 0,
+            bg_shift_high: 0,
+            attr_shift_low: 0,
+            attr_shift_high: 0,
+            frame: vec![vec![0; SCREEN_SIZE.0]; SCREEN_SIZE.1],
             cycles: 0,
+            suppress_vblank: false,
+            nmi_edge: false,
+            odd_frame: false,
+            clocks: 0,
             lines: 0,
             frames: 0,
-            buffer_2006: (0, true),
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            latch: 0,
         }
     }
 
-    /// パターンテーブルにキャラクタROMをしまう
-    pub fn load_pattern_table(&mut self, chrs: Vec<u8>) {
-        assert_eq!(0x2000, chrs.len());
-        for (i, c )in chrs.into_iter().enumerate() {
-            self.vram[i] = c;
-        }
+    /// NMI 出力
+    /// VBLANK フラグと PPUCTRL の V が両方立っている間 true
+    /// CPU は立ち上がりを見て NMI を起こす
+    pub fn nmi(&self) -> bool {
+        self.register.ppustatus & 0b1000_0000 != 0 && self.register.ppuctrl & 0b1000_0000 != 0
     }
 
-    fn blank_asseted(&mut self) -> bool {
-        self.register.ppuctrl & 0b1000_0000 > 0
+    /// 前に呼ばれてから NMI 出力が立ち上がっていたら true
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::replace(&mut self.nmi_edge, false)
+    }
+    /// 今描いているところ (ライン, ドット)
+    /// ドット 1-256 でライン上の x = 0-255 のピクセルを出力する
+    pub fn beam(&self) -> (usize, usize) {
+        (self.lines, self.cycles)
     }
 
-    pub fn add_cpu(&mut self, cpu: Rc<RefCell<cpu::Cpu>>) {
-        self.ppu_bus.cpu = Rc::downgrade(&cpu);
+    /// 描画中のフレームのピクセル [EEECCCCCC]: u16
+    /// ビームより前はこのフレーム, 後ろは前のフレームの値が入っている
+    pub fn pixel(&self, x: usize, y: usize) -> u16 {
+        self.frame[y][x]
     }
+
     // cyclesはppuが実行していいサイクル数
     // 1 Ppu cycle で 1dot処理
     // (256, 240), 内部では(341, 262)
@@ -191,44 +277,313 @@ impl Ppu {
     // line 241: VBLANKフラグが立ちNMI割り込みが発生
     // line 261: pre-render scanline: VBLANKフラグが降ろされる
     pub fn run(&mut self, cycles: usize) {
-        // println!("line@ppu: {} cycles: {} += {}", self.lines, self.cycles, cycles);
-        self.cycles += cycles;
-        if self.cycles > 341 {
-            self.cycles -= 341;
-            self.lines += 1;
+        for _ in 0..cycles {
+            self.step();
         }
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.register.ppumask & 0b0001_1000 != 0
+    }
 
-        match self.lines {
-            0..=239 => {
-                // nothing to do
-                // println!("visible scanline"),
+    /// 1 ドット進める
+    fn step(&mut self) {
+        match (self.lines, self.cycles) {
+            (0..=239, _) => {
+                self.fetch();
+                if (1..=256).contains(&self.cycles) {
+                    self.output_pixel();
+                }
             }
-            240 => {
-                // println!("post render scanline");
+            (240, 0) => {
+                // post render scanline
                 // 240line = 240 * 341 cycle 目にできた画面を転送
-                println!("{} frame", self.frames);
                 self.frames += 1;
                 let screen = self.build_screen();
                 self.ppu_bus.screen.draw(screen);
             }
-            241 => {
-                dbg!(&self.register);
-                // interrupt NMI if VBLANK is asseted
-                if self.blank_asseted() {
-                    let cpu = self.ppu_bus.cpu.upgrade().unwrap();
-                    cpu.borrow_mut().set_nmi_flag();
+            (241, 1) => {
+                // VBLANK 開始, 直前に $2002 を読まれていたら立たない
+                if !self.suppress_vblank {
+                    self.register.ppustatus |= 0b1000_0000;
+                    self.nmi_edge |= self.nmi();
                 }
+                self.suppress_vblank = false;
             }
-            242..=260 => {
-                // println!("vertical blanking line");
+            (261, _) => {
+                if self.cycles == 1 {
+                    // VBLANK, sprite 0 hit, overflow を下ろす
+                    self.register.ppustatus &= !0b1110_0000;
+                }
+                self.fetch();
+                if (280..=304).contains(&self.cycles) && self.rendering_enabled() {
+                    self.copy_vertical();
+                }
             }
-            // last line
-            261 => {
-                // println!("pre-render scanline");
+            _ => {}
+        }
+
+        self.clocks += 1;
+        self.cycles += 1;
+        // 奇数フレームは pre-render line の dot 339 から次のフレームの dot 0 に飛ぶ
+        if self.lines == 261 && self.cycles == 340 && self.odd_frame && self.rendering_enabled() {
+            self.cycles = 341;
+        }
+        if self.cycles == 341 {
+            self.cycles = 0;
+            self.lines += 1;
+            if self.lines == INTERNAL_SIZE.1 {
                 self.lines = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+
+    /// 描画中のライン (visible, pre-render) のメモリアクセス
+    /// - dot 1-256: BG のタイル 32 個
+    /// - dot 257-320: 次のラインのスプライト 8 個
+    /// - dot 321-336: 次のラインの BG の最初のタイル 2 個
+    /// - dot 337-340: 使われないネームテーブルの読み込み
+    fn fetch(&mut self) {
+        if !self.rendering_enabled() {
+            return;
+        }
+        let dot = self.cycles;
+        if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+            self.shift_bg();
+            match (dot - 1) % 8 {
+                0 => {
+                    self.load_bg_shifters();
+                    self.bg_next_tile = self.fetch_vram(0x2000 | (self.v & 0x0fff));
+                }
+                2 => {
+                    let attr = self.fetch_vram(0x23c0 | (self.v & 0x0c00) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07));
+                    // 属性 1 byte は 32x32 px, そのうちどの 16x16 px か
+                    let shift = ((self.v >> 4) & 0x04) | (self.v & 0x02);
+                    self.bg_next_attr = (attr >> shift) & 0x03;
+                }
+                4 => self.bg_next_low = self.fetch_vram(self.bg_pattern_addr()),
+                6 => self.bg_next_high = self.fetch_vram(self.bg_pattern_addr() + 8),
+                7 => self.increment_x(),
+                _ => {}
+            }
+        }
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                self.load_bg_shifters();
+                self.copy_horizontal();
+                if self.lines == 261 {
+                    // pre-render line では次のライン (0) に表示するスプライトは選ばれない
+                    self.secondary_oam.clear();
+                    self.sprite0_in_line = false;
+                } else {
+                    self.evaluate_sprites();
+                }
+                self.sprite_patterns.clear();
+            }
+            258..=320 => self.fetch_sprite(dot),
+            338 | 340 => {
+                self.fetch_vram(0x2000 | (self.v & 0x0fff));
+            }
+            _ => {}
+        }
+    }
+
+    /// レンダリングのための vram の読み込み
+    /// アドレスバスを見ているマッパー (MMC3 の A12) に知らせる
+    fn fetch_vram(&mut self, addr: u16) -> u8 {
+        self.ppu_bus.mapper.borrow_mut().notify_ppu_address(addr, self.clocks);
+        self.read_vram(addr)
+    }
+
+    fn bg_pattern_addr(&self) -> u16 {
+        let bg_table = if self.register.ppuctrl & 0b0001_0000 != 0 { 0x1000 } else { 0x0000 };
+        let fine_y = (self.v >> 12) & 0x07;
+        bg_table + self.bg_next_tile as u16 * 16 + fine_y
+    }
+
+    /// 次のタイルをシフトレジスタの下位 8bit に入れる
+    fn load_bg_shifters(&mut self) {
+        self.bg_shift_low = (self.bg_shift_low & 0xff00) | self.bg_next_low as u16;
+        self.bg_shift_high = (self.bg_shift_high & 0xff00) | self.bg_next_high as u16;
+        let attr_low = if self.bg_next_attr & 0x01 != 0 { 0xff } else { 0x00 };
+        let attr_high = if self.bg_next_attr & 0x02 != 0 { 0xff } else { 0x00 };
+        self.attr_shift_low = (self.attr_shift_low & 0xff00) | attr_low;
+        self.attr_shift_high = (self.attr_shift_high & 0xff00) | attr_high;
+    }
+
+    fn shift_bg(&mut self) {
+        self.bg_shift_low <<= 1;
+        self.bg_shift_high <<= 1;
+        self.attr_shift_low <<= 1;
+        self.attr_shift_high <<= 1;
+    }
+
+    /// 8x8 か 8x16 か
+    fn sprite_height(&self) -> u16 {
+        if self.register.ppuctrl & 0b0010_0000 != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// 今のラインに Y 座標が重なるスプライトを OAM の順に最大 8 個 secondary OAM に入れる
+    /// スプライトは OAM の Y + 1 のラインから表示されるので, ここで選んだものは次のラインに出る
+    // TODO: 実機は dot 65-256 で 1 つずつ評価する
+    fn evaluate_sprites(&mut self) {
+        let height = self.sprite_height() as usize;
+        let line = self.lines;
+        let in_range = |y: u8| line.wrapping_sub(y as usize) < height;
+        self.secondary_oam.clear();
+        self.sprite0_in_line = false;
+        let mut n = 0;
+        while n < 64 && self.secondary_oam.len() < 8 {
+            let sprite = Sprite::new(&self.oam[n * 4..n * 4 + 4]);
+            if in_range(sprite.y) {
+                self.sprite0_in_line |= n == 0;
+                self.secondary_oam.push(sprite);
+            }
+            n += 1;
+        }
+        // 9 個目を探すときは n と一緒に m (バイトの位置) も進めてしまうバグがあり
+        // Y 以外のバイトを Y として比べる
+        let mut m = 0;
+        while n < 64 {
+            if in_range(self.oam[n * 4 + m]) {
+                self.register.ppustatus |= 0b0010_0000;
+                break;
+            }
+            n += 1;
+            m = (m + 1) & 0x03;
+        }
+    }
+
+    /// dot 257-320 で 8 ドットごとに 1 つずつスプライトのパターンを読む
+    /// 空きスロットはタイル $FF を読む
+    fn fetch_sprite(&mut self, dot: usize) {
+        let (slot, phase) = ((dot - 257) / 8, (dot - 257) % 8);
+        if phase != 4 && phase != 6 {
+            return;
+        }
+        let height = self.sprite_height();
+        let table = if self.register.ppuctrl & 0b0000_1000 != 0 { 0x1000 } else { 0x0000 };
+        let (sprite, row) = match self.secondary_oam.get(slot) {
+            Some(sprite) => (*sprite, (self.lines - sprite.y as usize) as u16 % height),
+            None => (Sprite::new(&[0xff; 4]), 0),
+        };
+        let addr = sprite.pattern_addr(row, height, table);
+        if phase == 4 {
+            let low = self.fetch_vram(addr);
+            if slot < self.secondary_oam.len() {
+                self.sprite_patterns.push((low, 0));
+            }
+        } else {
+            let high = self.fetch_vram(addr + 8);
+            if let Some(pattern) = self.sprite_patterns.get_mut(slot) {
+                pattern.1 = high;
+            }
+        }
+    }
+
+    /// dot 1-256 で 1 画素ずつ BG とスプライトを重ねて frame に描く
+    /// 同じ位置のスプライトは OAM の若い方が勝ち, その後で優先度ビットを見て BG と比べる
+    fn output_pixel(&mut self) {
+        let x = self.cycles - 1;
+        let mask = self.register.ppumask;
+        let mut bg = (0, 0);
+        // 左端 8px は m が 0 ならクリッピング
+        if mask & 0b0000_1000 != 0 && (x >= 8 || mask & 0b0000_0010 != 0) {
+            let bit = 0x8000 >> self.x;
+            let c = (self.bg_shift_low & bit != 0) as u16 | ((self.bg_shift_high & bit != 0) as u16) << 1;
+            let palette = (self.attr_shift_low & bit != 0) as u16 | ((self.attr_shift_high & bit != 0) as u16) << 1;
+            bg = (c, palette);
+        }
+
+        // (色, 属性, sprite 0 か)
+        let mut sprite = None;
+        // 左端 8px は M が 0 ならクリッピング
+        if mask & 0b0001_0000 != 0 && (x >= 8 || mask & 0b0000_0100 != 0) {
+            for (i, (s, (low, high))) in self.secondary_oam.iter().zip(&self.sprite_patterns).enumerate() {
+                let offset = x.wrapping_sub(s.x as usize);
+                if offset >= 8 {
+                    continue;
+                }
+                // 水平反転
+                let bit = if s.attr & 0x40 != 0 { offset } else { 7 - offset };
+                let c = ((low >> bit) & 0x01) | (((high >> bit) & 0x01) << 1);
+                if c != 0 {
+                    sprite = Some((c as u16, s.attr, i == 0 && self.sprite0_in_line));
+                    break;
+                }
             }
-            _ => panic!("line: {}", self.lines),
         }
+
+        if let Some((_, _, true)) = sprite {
+            if bg.0 != 0 {
+                self.hit_sprite0(x);
+            }
+        }
+        let addr = match sprite {
+            // 背面のスプライトは BG が透明なところだけ見える
+            Some((c, attr, _)) if attr & 0x20 == 0 || bg.0 == 0 => 0x3f10 + (attr & 0x03) as u16 * 4 + c,
+            _ if bg.0 != 0 => 0x3f00 + bg.1 * 4 + bg.0,
+            // 描画が無効で v がパレットを指しているとその色が出る
+            _ if !self.rendering_enabled() && self.v & 0x3f00 == 0x3f00 => self.v & 0x3fff,
+            _ => 0x3f00,
+        };
+        let color = self.read_vram(addr) as u16;
+        self.frame[self.lines][x] = color | ((mask as u16 & 0b1110_0000) << 1);
+    }
+
+    /// sprite 0 と BG の色 0 以外が重なった
+    /// x = 255 と, 左端 8px のクリッピングが有効なときの x = 0-7 では起きない
+    fn hit_sprite0(&mut self, x: usize) {
+        let clipped = x < 8 && self.register.ppumask & 0b0000_0110 != 0b0000_0110;
+        if x != 255 && !clipped {
+            self.register.ppustatus |= 0b0100_0000;
+        }
+    }
+
+    /// coarse X を進める, 32 を超えたら隣のネームテーブルへ
+    fn increment_x(&mut self) {
+        if self.v & 0x001f == 31 {
+            self.v &= !0x001f;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    /// fine Y を進める, 溢れたら coarse Y を進める
+    /// coarse Y は 29 で下のネームテーブルへ, 31 (属性テーブルの中) からは同じテーブルの 0 に戻る
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut y = (self.v & 0x03e0) >> 5;
+        if y == 29 {
+            y = 0;
+            self.v ^= 0x0800;
+        } else if y == 31 {
+            y = 0;
+        } else {
+            y += 1;
+        }
+        self.v = (self.v & !0x03e0) | (y << 5);
+    }
+
+    /// coarse X と水平方向のネームテーブル
+    fn copy_horizontal(&mut self) {
+        self.v = (self.v & !0x041f) | (self.t & 0x041f);
+    }
+
+    /// fine Y, coarse Y と垂直方向のネームテーブル
+    fn copy_vertical(&mut self) {
+        self.v = (self.v & !0x7be0) | (self.t & 0x7be0);
     }
 
     // TODO: readレジスタの動作を記述する
@@ -236,194 +591,182 @@ impl Ppu {
         match addr {
             0x2000 => self.register.ppuctrl,
             0x2001 => self.register.ppumask,
-            0x2002 => self.register.ppustatus,
-            0x2003 => self.register.oamaddr,
-            0x2004 => self.register.oamdata,
-            0x2005 => self.register.ppuscroll,
-            0x2006 => {
-                panic!("2006には読み込みがないはず");
+            0x2002 => {
+                // 下位 5bit は open bus
+                let data = (self.register.ppustatus & 0b1110_0000) | (self.latch & 0b0001_1111);
+                // 読むと VBLANK フラグが下りる
+                self.register.ppustatus &= !0b1000_0000;
+                // VBLANK が立つ 1 ドット前に読むとそのフレームは立たない (NMI も起きない)
+                if self.lines == 241 && self.cycles == 1 {
+                    self.suppress_vblank = true;
+                } else if self.lines == 241 && (2..=3).contains(&self.cycles) {
+                    // 立った直後に読むとフラグは見えるが NMI は起きない
+                    self.nmi_edge = false;
+                }
+                // $2005/$2006 の書き込み順をリセット
+                self.w = false;
+                data
             }
+            0x2003 => self.register.oamaddr,
+            0x2004 => self.oam[self.register.oamaddr as usize],
+            // 書き込み専用なので最後に書き込んだ値が見える (open bus)
+            0x2005 | 0x2006 => self.latch,
             0x2007 => {
-                // TODO: PPU mem addr += 1 or += 32
-                if true {
-                    self.buffer_2006.0 += 0x01;
+                let addr = self.v & 0x3fff;
+                let data = if addr >= 0x3f00 {
+                    // パレットはすぐ読めるが, バッファには下にあるネームテーブルが入る
+                    self.read_buffer = self.read_vram(addr - 0x1000);
+                    // パレットは 6bit しかないので上位 2bit は open bus
+                    self.read_vram(addr) | (self.latch & 0xc0)
                 } else {
-                    self.buffer_2006.0 += 0x20;
-                }
-
-                self.register.ppudata
+                    let data = self.read_buffer;
+                    self.read_buffer = self.read_vram(addr);
+                    data
+                };
+                self.increment_vram_addr();
+                data
             }
             _ => panic!("そんなppuれじすたない{:?}", addr),
         }
     }
-    // load 2006 3f // buf: [3f ??] count: 0
-    // load 2006 00 // buf: [3f 00] count: 1
-    // st 2006 4a // $2006:[3f 00] -> [3f 01] 3f00に4aを書きたい
-    // load 2006 10 buf:[10 00] or data:[3f 02]
-
     // TODO: writeレジスタの動作を記述する
     pub fn write_register(&mut self, addr: u16, data: u8) -> u8 {
+        self.latch = data;
         match addr {
             0x2000 => {
+                let nmi = self.nmi();
                 self.register.ppuctrl = data;
+                // VBLANK 中に V を立てると NMI が起きる
+                self.nmi_edge |= !nmi && self.nmi();
+                // NN は t のネームテーブルにも入る
+                self.t = (self.t & !0x0c00) | ((data as u16 & 0x03) << 10);
             }
             0x2001 => self.register.ppumask = data,
             0x2002 => self.register.ppustatus = data,
             0x2003 => self.register.oamaddr = data,
-            0x2004 => self.register.oamdata = data,
-            0x2005 => self.register.ppuscroll = data,
-            0x2006 => {
-                // 1st: hi: u8, 2nd: low: u8 -> u16
-                if self.buffer_2006.1 {
-                    // 1st
-                    self.buffer_2006.0 = (data as u16) << 8;
-                    self.buffer_2006.1 = !self.buffer_2006.1;
+            0x2004 => {
+                // 属性の bit 2-4 は存在しないので 0 が読める
+                let data = if self.register.oamaddr & 0x03 == 0x02 { data & 0xe3 } else { data };
+                self.oam[self.register.oamaddr as usize] = data;
+                self.register.oamaddr = self.register.oamaddr.wrapping_add(1);
+            }
+            0x2005 => {
+                if !self.w {
+                    // 1st: X スクロール [XXXXX xxx]
+                    self.t = (self.t & !0x001f) | (data as u16 >> 3);
+                    self.x = data & 0x07;
                 } else {
-                    // 2nd
-                    self.buffer_2006.0 += data as u16;
-                    self.buffer_2006.1 = !self.buffer_2006.1;
+                    // 2nd: Y スクロール [YYYYY yyy]
+                    self.t = (self.t & !0x73e0) | ((data as u16 & 0x07) << 12) | ((data as u16 >> 3) << 5);
                 }
+                self.w = !self.w;
             }
-            0x2007 => {
-                self.write_vram(self.buffer_2006.0, data);
-                // TODO: レジスタ見て切り替えるようにする
-                if true {
-                    self.buffer_2006.0 += 0x01;
+            0x2006 => {
+                if !self.w {
+                    // 1st: hi, bit 14 は 0 になる
+                    self.t = (self.t & 0x00ff) | ((data as u16 & 0x3f) << 8);
                 } else {
-                    self.buffer_2006.0 += 0x20;
+                    // 2nd: low, ここで v に反映される
+                    self.t = (self.t & 0xff00) | data as u16;
+                    self.v = self.t;
                 }
+                self.w = !self.w;
+            }
+            0x2007 => {
+                self.write_vram(self.v, data);
+                self.increment_vram_addr();
             }
             _ => panic!("そんなppuれじすたない{:?}", addr),
         }
         data
     }
 
-    // TODO: implement
-    /// vramを参照しつつnesのビットカラーでu8の2次元配列を作成する
-    pub fn build_screen(&mut self) -> Vec<Vec<u8>> {
-        // Color: xx GG BB RR: u8
-        // $1000 から 0x10(16 byte) ブロック刻み 256個
-        // ブロックは前半8byte = Low, 後半 = Highとして [hi low]: 2bit で1pixel
+    /// $2007 を読み書きした後に v を進める
+    /// PPUCTRL の I が 0 なら +1 (横), 1 なら +32 (縦)
+    fn increment_vram_addr(&mut self) {
+        let step = if self.register.ppuctrl & 0b0000_0100 != 0 { 0x20 } else { 0x01 };
+        self.v = self.v.wrapping_add(step) & 0x7fff;
+    }
 
+    /// 描き終わったフレームを返す, ついでにデバッグ画面を更新する
+    pub fn build_screen(&mut self) -> Vec<Vec<u16>> {
         let mut debug_screen: Vec<u8> = vec![];
-
-        // TODO: 今ネームテーブル属性テーブルは0番目しか使わない
-        // 1つのベクトルあたり、chrが1つはいっている
-        let mut chrs: Vec<Vec<u8>> = vec![];
-        // ネームテーブルを走査
-        let NAME_TABLE_HEAD = 0x2000;
-        for addr in NAME_TABLE_HEAD..NAME_TABLE_HEAD+0x03c0 {
-            // 0x2000: NAMETABLE HEAD
-            let sprite_idx = self.vram[addr] as usize;
-            // 16byteとばしで ADDR
-            let sprite_addr = sprite_idx * 16;
-            // 下位2bitしか使わないので注意
-            // 1キャラクタ8*8
-            type u2 = u8;
-            let mut chr: Vec<u2> = Vec::with_capacity(64);
-            // low, hi = low + 8
-            // FIXME: まだ背景キャラクタしか使わない
-            // 行ごとに
-            for j in sprite_addr..sprite_addr + 8 {
-                // 0..7
-                let low_line: u8 = self.vram[j as usize];
-                // 8..15
-                let high_line: u8 = self.vram[j as usize + 8];
-                let low_line = format!("{:08b}", low_line);
-                let high_line = format!("{:08b}", high_line);
-                // via string
-                let chr_dots = izip!(low_line.chars(), high_line.chars())
-                    .map(|(l, h)| l as i32 - '0' as i32 + ((h as i32 - '0' as i32) << 1))
-                    .map(|a| a as u2)
-                    .collect::<Vec<u2>>();
-                chr.extend(chr_dots);
-            }
-
-            if sprite_addr != 0 {
-                log_1(&format!("sprite_addr: 0x{:x}, sprite_idx: {}, chr: {:?}", sprite_addr, sprite_idx, chr).into());
-            }
-            assert!(chr.len() == 64);
-
-            chrs.push(chr);
-        }
-
-        // length: 16 * 16だが16*15までしかホントは使われない
-        // 中に
-        let mut palettes = Vec::with_capacity(16 * 16);
-        for i in 0..0x40 {
-            // 4つブロック分の属性が入ってる
-            // 0x23c0: attr table head
-            let attr4: u8 = self.vram[0x23C0 + i];
-            // each 2 bits
-            for (j, sht) in vec![
-                (0b11_00_00_00, 6),
-                (0b00_11_00_00, 4),
-                (0b00_00_11_00, 2),
-                (0b00_00_00_11, 0),
-            ] {
-                // 0x3f00: bg palette head
-                // block_attr: 2bit
-                let block_attr = (attr4 & j) >> sht;
-                // Z型
-                let palette_addr = 0x3f00
-                    // どのパレット使うか
-                    + match block_attr {
-                        0x00 => 0,
-                        0x01 => 4,
-                        0x10 => 8,
-                        0x11 => 0xc0,
-                        _ => panic!("wtf block attr"),
-                    };
-                // u8(index) x 4色
-                let palette: Vec<u8> = self.vram[palette_addr..palette_addr+0x04].to_vec();
-                assert!(palette.len() == 4);
-                palettes.push(palette);
-            }
-        }
-        // each block(16bytes)
-        // TODO: まだバックグラウンドのキャラクタROMしか使えない
-        let mut pixels = vec![vec![0; SCREEN_SIZE.0]; SCREEN_SIZE.1];
-
-        // 240
-        for y in 0..SCREEN_SIZE.1 {
-            // 256
-            for x in 0..SCREEN_SIZE.0 {
-                // chrs(1列32個:thinking_face:)のインデックス (x, y) -> i を計算
-                // キャラクタが横に32個並ぶ
-                // (8 x 8)なんブロック目
-                // chr_x: 0..30, chr_y: 0..32
-                let (chr_x, chr_y) = (x / 8, y / 8);
-
-                let chr = &chrs[chr_y * 32 + chr_x];
-                // u2型: 0-3
-                let c = chr[(y % 8) * 8 + x % 8];
-
-                let palette = &palettes[y / 16 * 16 + x / 16];
-                assert!(palette.len() == 4);
-                // 色をセット
-                pixels[y][x] = palette[c as usize];
-            }
-        }
-        assert!(pixels.len() * pixels[0].len() == SCREEN_SIZE.0 * SCREEN_SIZE.1);
-        debug_screen.extend(palettes.clone().into_iter().flatten().collect::<Vec<u8>>());
-        debug_screen.extend(self.vram.clone().into_iter());
+        // パレット
+        debug_screen.extend((0x3f00..0x3f20).map(|addr| self.read_vram(addr)).collect::<Vec<u8>>());
         debug_screen.extend(vec![20; 500]);
-        debug_screen.extend(self.vram.clone()[0x2000..0x23c0].into_iter());
+        // パターンテーブルはカートリッジ側 (CHR RAM なら書き込まれた内容) を見る
+        debug_screen.extend((0x0..0x4000).map(|addr| self.read_vram(addr)).collect::<Vec<u8>>());
         debug_screen.extend(vec![20; 500]);
-        debug_screen.extend(self.vram.clone()[0x0..0x1000].into_iter());
+        // 次のフレームの先頭で使うネームテーブル
+        let name_table_head = 0x2000 | (self.t & 0x0c00);
+        debug_screen.extend((name_table_head..name_table_head + 0x3c0).map(|addr| self.read_vram(addr)).collect::<Vec<u8>>());
         self.ppu_bus.screen.draw_debug(debug_screen);
-        pixels
+        self.frame.clone()
     }
 
     /// vramを読む
+    /// - $0000-$1FFF: パターンテーブル (カートリッジ)
+    /// - $2000-$2FFF: ネームテーブル, $3000-$3EFF はそのミラー
+    /// - $3F00-$3FFF: パレット
+    ///
+    /// $4000 以上は $0000-$3FFF のミラー
     pub fn read_vram(&self, addr: u16) -> u8 {
-        self.vram[addr as usize]
+        let addr = addr & 0x3fff;
+        match addr {
+            0x0000..=0x1fff => self.ppu_bus.mapper.borrow_mut().ppu_read(addr),
+            0x2000..=0x3eff => self.nametables[self.mirror_nametable(addr)],
+            _ => {
+                let color = self.palette[Ppu::mirror_palette(addr)];
+                // PPUMASK の G が立っていたらモノクロ (明度だけ残す)
+                if self.register.ppumask & 0b0000_0001 != 0 {
+                    color & 0x30
+                } else {
+                    color
+                }
+            }
+        }
     }
 
     pub fn write_vram(&mut self, addr: u16, data: u8) -> u8 {
-        self.vram[addr as usize] = data;
+        let addr = addr & 0x3fff;
+        match addr {
+            0x0000..=0x1fff => self.ppu_bus.mapper.borrow_mut().ppu_write(addr, data),
+            0x2000..=0x3eff => {
+                let addr = self.mirror_nametable(addr);
+                self.nametables[addr] = data;
+            }
+            _ => self.palette[Ppu::mirror_palette(addr)] = data & 0x3f,
+        }
         data
     }
+
+    /// ネームテーブルのアドレスをミラーリングに従って nametables の位置にする
+    /// | $2000 | $2400 |
+    /// | $2800 | $2C00 |
+    /// CIRAM は 2 KiB (2 枚分) しかないので four screen 以外は 2 枚を使い回す
+    fn mirror_nametable(&self, addr: u16) -> usize {
+        let addr = addr as usize & 0x0fff;
+        let (table, offset) = (addr / 0x400, addr % 0x400);
+        let table = match self.ppu_bus.mapper.borrow().mirroring() {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertical => table % 2,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => table,
+        };
+        table * 0x400 + offset
+    }
+
+    /// パレットは 32 byte ごとにミラーされる
+    /// スプライトパレットの 0 番 ($3F10, $3F14, $3F18, $3F1C) は BG パレットの 0 番と共有
+    fn mirror_palette(addr: u16) -> usize {
+        let addr = addr as usize & 0x1f;
+        if addr & 0x13 == 0x10 {
+            addr & 0x0f
+        } else {
+            addr
+        }
+    }
 }
 
 /// VRAM_SIZE = 0x1fff
@@ -440,7 +783,399 @@ impl Index<usize> for VRAM {
     }
 }
 
+#[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::cartridge::{ines_header, Cartridge, CHARACTER_ROM_SIZE, PROGRAM_ROM_SIZE};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
     #[test]
     fn hjoge() {}
+
+    fn ppu(binary: Vec<u8>) -> (Ppu, mapper::SharedMapper) {
+        let mapper = Rc::new(RefCell::new(mapper::new(Cartridge::parse(&binary).unwrap()).unwrap()));
+        (Ppu::new(screen::Screen::new(), mapper.clone()), mapper)
+    }
+
+    fn nrom(flags6: u8) -> Ppu {
+        let mut binary = ines_header([1, 1, flags6, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        binary.extend(vec![0; PROGRAM_ROM_SIZE + CHARACTER_ROM_SIZE]);
+        ppu(binary).0
+    }
+
+    #[test]
+    fn nametable_mirroring() {
+        // horizontal: $2000 = $2400, $2800 = $2C00
+        let mut ppu = nrom(0x00);
+        ppu.write_vram(0x2001, 1);
+        ppu.write_vram(0x2801, 2);
+        assert_eq!(ppu.read_vram(0x2401), 1);
+        assert_eq!(ppu.read_vram(0x2c01), 2);
+        // $3000-$3EFF は $2000-$2EFF のミラー
+        assert_eq!(ppu.read_vram(0x3001), 1);
+
+        // vertical: $2000 = $2800, $2400 = $2C00
+        let mut ppu = nrom(0x01);
+        ppu.write_vram(0x2001, 1);
+        ppu.write_vram(0x2401, 2);
+        assert_eq!(ppu.read_vram(0x2801), 1);
+        assert_eq!(ppu.read_vram(0x2c01), 2);
+
+        // four screen: 4 枚とも別
+        let mut ppu = nrom(0x08);
+        for (i, table) in [0x2000, 0x2400, 0x2800, 0x2c00].iter().enumerate() {
+            ppu.write_vram(*table, i as u8);
+        }
+        for (i, table) in [0x2000, 0x2400, 0x2800, 0x2c00].iter().enumerate() {
+            assert_eq!(ppu.read_vram(*table), i as u8);
+        }
+    }
+
+    #[test]
+    fn scroll_registers() {
+        let mut ppu = nrom(0x00);
+        ppu.write_register(0x2000, 0x00);
+        ppu.read_register(0x2002);
+        ppu.write_register(0x2005, 0x7d);
+        assert_eq!((ppu.t, ppu.x, ppu.w), (0x000f, 5, true));
+        ppu.write_register(0x2005, 0x5e);
+        assert_eq!((ppu.t, ppu.w), (0x616f, false));
+        ppu.write_register(0x2006, 0x3d);
+        assert_eq!(ppu.t, 0x3d6f);
+        ppu.write_register(0x2006, 0xf0);
+        assert_eq!((ppu.t, ppu.v), (0x3df0, 0x3df0));
+
+        // $2002 を読むと 1 回目の書き込みに戻る
+        ppu.write_register(0x2006, 0x21);
+        ppu.read_register(0x2002);
+        ppu.write_register(0x2006, 0x23);
+        ppu.write_register(0x2006, 0x45);
+        assert_eq!(ppu.v, 0x2345);
+
+        // fine Y が溢れたら coarse Y, 29 で下のネームテーブルへ
+        ppu.v = 0x7000 | (29 << 5);
+        ppu.increment_y();
+        assert_eq!(ppu.v, 0x0800);
+        ppu.v = 0x041f;
+        ppu.increment_x();
+        assert_eq!(ppu.v, 0x0000);
+    }
+
+    /// UNROM (CHR RAM), vertical
+    fn unrom() -> Ppu {
+        let mut binary = ines_header([2, 0, 0x21, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        binary.extend(vec![0; 2 * PROGRAM_ROM_SIZE]);
+        ppu(binary).0
+    }
+
+    #[test]
+    fn horizontal_scroll() {
+        let mut ppu = unrom();
+        // タイル 1 は全部色 1
+        for i in 0..8 {
+            ppu.write_vram(0x0010 + i, 0xff);
+        }
+        // $2400 の左上にタイル 1
+        ppu.write_vram(0x2400, 0x01);
+        ppu.write_vram(0x3f01, 0x30);
+        // 左端 8px も表示する
+        ppu.write_register(0x2001, 0x0a);
+        // $2400 から右に 4 px
+        ppu.write_register(0x2000, 0x01);
+        ppu.write_register(0x2005, 0x04);
+        ppu.write_register(0x2005, 0x00);
+
+        // pre-render line で t が v にコピーされてから 1 ライン描く
+        ppu.run(341 * 263);
+        assert_eq!(ppu.frame[0][0], 0x30);
+        assert_eq!(ppu.frame[0][3], 0x30);
+        assert_eq!(ppu.frame[0][4], 0x00);
+    }
+
+    #[test]
+    fn sprites() {
+        let mut ppu = unrom();
+        // タイル 1 は左端の列だけ色 1
+        for i in 0..8 {
+            ppu.write_vram(0x0010 + i, 0x80);
+        }
+        ppu.write_vram(0x2000, 0x01);
+        ppu.write_vram(0x3f01, 0x30);
+        ppu.write_vram(0x3f11, 0x16);
+
+        let mut oam = vec![];
+        // 水平反転
+        oam.extend(&[9, 1, 0x40, 16]);
+        // BG の後ろ
+        oam.extend(&[0, 1, 0x20, 0]);
+        // 同じラインに 9 個
+        for i in 0..9 {
+            oam.extend(&[19, 1, 0x00, 100 + 8 * i]);
+        }
+        oam.resize(0x100, 0xff);
+        ppu.write_register(0x2003, 0x00);
+        for data in oam {
+            ppu.write_register(0x2004, data);
+        }
+        ppu.write_register(0x2001, 0x1e);
+        ppu.run(341 * 262);
+
+        // OAM の Y + 1 のラインから表示される
+        assert_eq!(ppu.frame[9][23], 0x00);
+        assert_eq!(ppu.frame[10][16], 0x00);
+        assert_eq!(ppu.frame[10][23], 0x16);
+        assert_eq!(ppu.frame[1][0], 0x30);
+        assert_eq!(ppu.frame[20][100 + 8 * 7], 0x16);
+        assert_eq!(ppu.frame[20][100 + 8 * 8], 0x00);
+    }
+
+    #[test]
+    fn odd_frame_skip() {
+        let mut ppu = unrom();
+        ppu.write_register(0x2001, 0x08);
+        ppu.run(341 * 262);
+        assert_eq!((ppu.lines, ppu.cycles), (0, 0));
+        // 奇数フレームは 1 ドット短い
+        ppu.run(341 * 262 - 1);
+        assert_eq!((ppu.lines, ppu.cycles), (0, 0));
+        ppu.run(341 * 262);
+        assert_eq!((ppu.lines, ppu.cycles), (0, 0));
+    }
+
+    #[test]
+    fn vblank() {
+        let mut ppu = nrom(0x00);
+        ppu.write_register(0x2000, 0x80);
+        // (241, 1) で立つ
+        ppu.run(341 * 241 + 1);
+        assert!(!ppu.nmi());
+        ppu.run(1);
+        assert!(ppu.nmi());
+
+        // VBLANK 中に PPUCTRL の V を立て直すと NMI がもう一度上がる
+        ppu.write_register(0x2000, 0x00);
+        assert!(!ppu.nmi());
+        ppu.run(341);
+        ppu.write_register(0x2000, 0x80);
+        assert!(ppu.nmi());
+
+        // 読むと下りる
+        assert_eq!(ppu.read_register(0x2002) & 0x80, 0x80);
+        assert_eq!(ppu.read_register(0x2002) & 0x80, 0x00);
+        assert!(!ppu.nmi());
+
+        // pre-render line で下りる
+        let mut ppu = nrom(0x00);
+        ppu.run(341 * 241 + 2);
+        assert!(ppu.register.ppustatus & 0x80 != 0);
+        ppu.run(341 * 20);
+        assert!(ppu.register.ppustatus & 0x80 == 0);
+    }
+
+    #[test]
+    fn vblank_read_race() {
+        // 立つ 1 ドット前に読むとそのフレームは立たない
+        let mut ppu = nrom(0x00);
+        ppu.write_register(0x2000, 0x80);
+        ppu.run(341 * 241 + 1);
+        assert_eq!(ppu.read_register(0x2002) & 0x80, 0x00);
+        ppu.run(1);
+        assert!(!ppu.nmi());
+        assert_eq!(ppu.read_register(0x2002) & 0x80, 0x00);
+
+        // 次のフレームは立つ
+        ppu.run(341 * 262);
+        assert!(ppu.nmi());
+    }
+
+    #[test]
+    fn nmi_edge_latch() {
+        let mut ppu = nrom(0x00);
+        ppu.write_register(0x2000, 0x80);
+        ppu.run(341 * 241 + 2);
+        // 立った次のドットで読むとフラグは見えるが NMI は起きない
+        assert_eq!(ppu.read_register(0x2002) & 0x80, 0x80);
+        assert!(!ppu.take_nmi());
+
+        // 少し後に読んで下ろしても立ち上がりは残る
+        let mut ppu = nrom(0x00);
+        ppu.write_register(0x2000, 0x80);
+        ppu.run(341 * 241 + 10);
+        assert_eq!(ppu.read_register(0x2002) & 0x80, 0x80);
+        assert!(!ppu.nmi());
+        assert!(ppu.take_nmi());
+        assert!(!ppu.take_nmi());
+
+        // VBLANK 中に V を立て直すともう一度
+        ppu.write_register(0x2000, 0x00);
+        ppu.run(341 * 262);
+        assert!(!ppu.take_nmi());
+        ppu.write_register(0x2000, 0x80);
+        assert!(ppu.take_nmi());
+
+        // 立つ 1 ドット前に読んだら起きない
+        let mut ppu = nrom(0x00);
+        ppu.write_register(0x2000, 0x80);
+        ppu.run(341 * 241 + 1);
+        ppu.read_register(0x2002);
+        ppu.run(1);
+        assert!(!ppu.take_nmi());
+    }
+
+    #[test]
+    fn mid_frame_palette_change() {
+        let mut ppu = unrom();
+        ppu.write_vram(0x3f00, 0x01);
+        ppu.run(341 * 100 + 129);
+        ppu.write_vram(0x3f00, 0x02);
+        ppu.run(341 * 162);
+        assert_eq!(ppu.frame[99][255], 0x01);
+        assert_eq!(ppu.frame[100][127], 0x01);
+        assert_eq!(ppu.frame[100][128], 0x02);
+    }
+
+    #[test]
+    fn ppumask() {
+        let mut ppu = unrom();
+        // タイル 1 は全部色 1
+        for i in 0..8 {
+            ppu.write_vram(0x0010 + i, 0xff);
+        }
+        for i in 0..32 {
+            ppu.write_vram(0x2000 + i, 0x01);
+        }
+        ppu.write_vram(0x3f00, 0x0f);
+        ppu.write_vram(0x3f01, 0x16);
+
+        // 左端 8px はクリッピングされて背景色
+        ppu.write_register(0x2001, 0x08);
+        ppu.run(341 * 263);
+        assert_eq!(ppu.frame[0][7], 0x0f);
+        assert_eq!(ppu.frame[0][8], 0x16);
+
+        // モノクロ + 赤と青を強調
+        ppu.write_register(0x2001, 0b1010_1011);
+        ppu.run(341 * 262);
+        assert_eq!(ppu.frame[0][0], 0x10 | (0b101 << 6));
+        assert_eq!(ppu.frame[0][8], 0x10 | (0b101 << 6));
+        // $2007 からのパレットの読み出しにもかかる
+        ppu.write_register(0x2006, 0x3f);
+        ppu.write_register(0x2006, 0x01);
+        assert_eq!(ppu.read_register(0x2007) & 0x3f, 0x10);
+
+        // レンダリングを止めると背景色だけになる
+        ppu.write_register(0x2006, 0x20);
+        ppu.write_register(0x2006, 0x00);
+        ppu.write_register(0x2001, 0x00);
+        ppu.run(341 * 262);
+        assert_eq!(ppu.frame[0][8], 0x0f);
+    }
+
+    fn write_oam(ppu: &mut Ppu, sprites: &[[u8; 4]]) {
+        ppu.write_register(0x2003, 0x00);
+        for i in 0..64 {
+            for data in sprites.get(i).unwrap_or(&[0xff; 4]) {
+                ppu.write_register(0x2004, *data);
+            }
+        }
+    }
+
+    #[test]
+    fn sprite0_hit() {
+        let mut ppu = unrom();
+        // タイル 1 は全部色 1, 一番上の行に並べる
+        for i in 0..8 {
+            ppu.write_vram(0x0010 + i, 0xff);
+        }
+        for i in 0..32 {
+            ppu.write_vram(0x2000 + i, 0x01);
+        }
+        write_oam(&mut ppu, &[[3, 1, 0x20, 20]]);
+        ppu.write_register(0x2001, 0x1e);
+
+        // ライン 4 の x = 20 (ドット 21) で立つ
+        ppu.run(341 * 4 + 21);
+        assert_eq!(ppu.read_register(0x2002) & 0x40, 0x00);
+        ppu.run(1);
+        assert_eq!(ppu.read_register(0x2002) & 0x40, 0x40);
+        // pre-render line で下りる
+        ppu.run(341 * (261 - 4));
+        assert_eq!(ppu.read_register(0x2002) & 0x40, 0x00);
+
+        // 左端 8px がクリッピングされていると起きない
+        write_oam(&mut ppu, &[[3, 1, 0x00, 0]]);
+        ppu.write_register(0x2001, 0x1c);
+        ppu.run(341 * 20);
+        assert_eq!(ppu.read_register(0x2002) & 0x40, 0x00);
+    }
+
+    #[test]
+    fn sprite_overflow() {
+        let mut ppu = unrom();
+        ppu.write_register(0x2001, 0x10);
+        let mut sprites = vec![[50, 0, 0, 0]; 9];
+        write_oam(&mut ppu, &sprites);
+        ppu.run(341 * 52);
+        assert_eq!(ppu.read_register(0x2002) & 0x20, 0x20);
+
+        // 8 個しかなくても, 9 個目を探すときに 10 番のタイル ID を Y として見てしまう
+        ppu.run(341 * (262 - 52));
+        assert_eq!(ppu.read_register(0x2002) & 0x20, 0x00);
+        sprites[8] = [0xff, 0xff, 0xff, 0xff];
+        sprites.push([0xff, 50, 0xff, 0xff]);
+        write_oam(&mut ppu, &sprites);
+        ppu.run(341 * 52);
+        assert_eq!(ppu.read_register(0x2002) & 0x20, 0x20);
+    }
+
+    #[test]
+    fn ppudata_read_buffer() {
+        let mut ppu = nrom(0x00);
+        ppu.write_vram(0x2000, 0x11);
+        ppu.write_vram(0x2020, 0x22);
+        ppu.write_vram(0x2f00, 0x33);
+        ppu.write_vram(0x3f00, 0x0f);
+
+        // +32
+        ppu.write_register(0x2000, 0x04);
+        ppu.write_register(0x2006, 0x20);
+        ppu.write_register(0x2006, 0x00);
+        // 1 回目は古いバッファ
+        assert_eq!(ppu.read_register(0x2007), 0x00);
+        assert_eq!(ppu.read_register(0x2007), 0x11);
+        assert_eq!(ppu.read_register(0x2007), 0x22);
+
+        // パレットはすぐ読めて, バッファには $2F00 が入る
+        ppu.write_register(0x2000, 0x00);
+        ppu.write_register(0x2006, 0x3f);
+        ppu.write_register(0x2006, 0x00);
+        assert_eq!(ppu.read_register(0x2007), 0x0f);
+        assert_eq!(ppu.read_buffer, 0x33);
+        assert_eq!(ppu.v, 0x3f01);
+    }
+
+    #[test]
+    fn palette_mirroring() {
+        let mut ppu = nrom(0x00);
+        ppu.write_vram(0x3f10, 0x0f);
+        assert_eq!(ppu.read_vram(0x3f00), 0x0f);
+        ppu.write_vram(0x3f05, 0x21);
+        assert_eq!(ppu.read_vram(0x3f25), 0x21);
+        assert_eq!(ppu.read_vram(0x3f15), 0x00);
+    }
+
+    #[test]
+    fn chr_ram_is_writable_through_2007() {
+        // UNROM, CHR ROM 0 ページ
+        let mut binary = ines_header([2, 0, 0x20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        binary.extend(vec![0; 2 * PROGRAM_ROM_SIZE]);
+        let (mut ppu, mapper) = ppu(binary);
+        ppu.write_register(0x2006, 0x12);
+        ppu.write_register(0x2006, 0x34);
+        ppu.write_register(0x2007, 0xab);
+        assert_eq!(ppu.read_vram(0x1234), 0xab);
+        assert_eq!(mapper.borrow().chr().as_slice()[0x1234], 0xab);
+        assert!(mapper.borrow().chr().is_ram());
+    }
 }
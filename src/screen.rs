@@ -40,6 +40,21 @@ pub fn mapping_color(color: u8) -> (u8, u8, u8) {
     }
 }
 
+/// PPUMASK の色強調 [BGR]: u8 をかける
+/// 強調されていない色を 1 bit ごとに約 0.816 倍に暗くする
+pub fn emphasize((r, g, b): (u8, u8, u8), emphasis: u8) -> (u8, u8, u8) {
+    let dim = |c: u8, own: u8| {
+        let others = (emphasis & !own & 0x07).count_ones();
+        (0..others).fold(c as u16, |c, _| c * 209 / 256) as u8
+    };
+    (dim(r, 0x01), dim(g, 0x02), dim(b, 0x04))
+}
+
+/// PPU のピクセル [EEECCCCCC]: u16 を RGB にする
+pub fn pixel_to_rgb(pixel: u16) -> (u8, u8, u8) {
+    emphasize(mapping_color((pixel & 0x3f) as u8), (pixel >> 6) as u8)
+}
+
 impl Screen {
     pub fn new() -> Screen {
         Screen {
@@ -51,10 +66,16 @@ impl Screen {
     /// draw screen
     /// # Arguments
     /// pixels: nesの世界でのカラーの色が来る
-    /// u6すなわち64個のうちのどれかの色を指定する
-    pub fn draw(&mut self, pixels: Vec<Vec<u8>>) {
+    /// [EEECCCCCC]: 下位 6bit が64個のうちのどれかの色, 上位 3bit が色強調 (BGR)
+    pub fn draw(&mut self, pixels: Vec<Vec<u16>>) {
         print!("draw completed");
-        self.screen = self.convert_screen_to_image(pixels, SCREEN_SIZE);
+        let mut data = Vec::with_capacity(SCREEN_SIZE.0 * SCREEN_SIZE.1 * 4);
+        for pixel in pixels.iter().flatten() {
+            let (r, g, b) = pixel_to_rgb(*pixel);
+            // rgba
+            data.extend(&[r, g, b, 255]);
+        }
+        self.screen = data;
     }
 
     pub fn draw_debug(&mut self, pixels: Vec<u8>) {